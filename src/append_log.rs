@@ -0,0 +1,228 @@
+//! A log of records appended by multiple processes.
+//!
+//! Every record is stored as a frame consisting of its length and its CRC-32
+//! checksum, both little-endian `u32`s, followed by the record itself. An
+//! [`AppendLog`] writes whole batches of frames with a single write to a file
+//! opened with `O_APPEND`, while holding an exclusive lock taken through a
+//! handle of its own, so frames of concurrent writers never interleave, even
+//! if they share the log. A [`LogReader`] holds a shared lock
+//! and validates every frame it reads, which catches frames torn by a crash.
+//! Appending cuts off a frame torn at the end of the log before writing.
+//!
+//! # Example
+//!
+//! ```
+//! use async_fd_lock::append_log::AppendLog;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let log = AppendLog::open(dir.path().join("events.log"))?;
+//! log.append(b"started")?;
+//! log.append_batch([&b"working"[..], b"stopped"])?;
+//!
+//! let records = log.reader()?.collect::<std::io::Result<Vec<_>>>()?;
+//! assert_eq!(records, [&b"started"[..], b"working", b"stopped"]);
+//! # std::io::Result::Ok(())
+//! ```
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::blocking::{LockRead, LockWrite, LockedBufReader};
+use crate::checksum::crc32;
+
+const HEADER_LEN: usize = 8;
+
+/// A log file that records are appended to.
+#[derive(Debug)]
+pub struct AppendLog {
+    path: PathBuf,
+    /// The end of the frames known to be complete, where checking for a torn
+    /// frame starts.
+    verified: AtomicU64,
+}
+
+/// Reads the records of an [`AppendLog`] while holding a shared lock on it.
+///
+/// Appends wait until the reader is dropped. Reading stops after the first
+/// invalid frame, which is reported as [`ErrorKind::InvalidData`], or
+/// [`ErrorKind::UnexpectedEof`] if the log ends in the middle of a frame.
+#[derive(Debug)]
+pub struct LogReader {
+    reader: LockedBufReader<File>,
+    offset: u64,
+    failed: bool,
+}
+
+impl AppendLog {
+    /// Opens the log at `path`, creating it if it does not exist.
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let log = Self {
+            path: path.into(),
+            verified: AtomicU64::new(0),
+        };
+        log.open_file()?;
+        Ok(log)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends a single record, returning the offset of its frame.
+    pub fn append(&self, record: &[u8]) -> io::Result<u64> {
+        self.append_batch([record])
+    }
+
+    /// Appends several records contiguously, returning the offset of the
+    /// first frame.
+    pub fn append_batch<I>(&self, records: I) -> io::Result<u64>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let mut frames = Vec::new();
+        for record in records {
+            let record = record.as_ref();
+            let len = u32::try_from(record.len())
+                .map_err(|_| io::Error::new(ErrorKind::InvalidInput, "record too large"))?;
+            frames.extend_from_slice(&len.to_le_bytes());
+            frames.extend_from_slice(&crc32(record).to_le_bytes());
+            frames.extend_from_slice(record);
+        }
+
+        let mut guard = self.open_file()?.lock_write()?;
+        let offset = self.repair_tail(guard.inner())?;
+        guard.write_all(&frames)?;
+        guard.release()?;
+        self.verified
+            .store(offset + frames.len() as u64, Ordering::Relaxed);
+        Ok(offset)
+    }
+
+    /// Opens a handle of the log of its own, which `flock` locks
+    /// independently of the others.
+    fn open_file(&self) -> io::Result<File> {
+        OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&self.path)
+    }
+
+    /// Truncates a frame torn at the end of the log, returning the new length.
+    /// Must be called while holding the exclusive lock through `file`.
+    fn repair_tail(&self, file: &File) -> io::Result<u64> {
+        let len = file.metadata()?.len();
+        let mut offset = self.verified.load(Ordering::Relaxed);
+        if offset > len {
+            // The log was truncated by someone else.
+            offset = 0;
+        }
+        let mut reader = BufReader::new(file);
+        reader.seek(SeekFrom::Start(offset))?;
+        let torn = loop {
+            match read_frame(&mut reader, offset) {
+                Ok(Some(record)) => offset += (HEADER_LEN + record.len()) as u64,
+                Ok(None) => break false,
+                Err(error) if error.kind() == ErrorKind::UnexpectedEof => break true,
+                // A crash may leave the end of the last frame unwritten
+                // without shortening it.
+                Err(error) if error.kind() == ErrorKind::InvalidData => {
+                    if !reader.fill_buf()?.is_empty() {
+                        return Err(error);
+                    }
+                    break true;
+                }
+                Err(error) => return Err(error),
+            }
+        };
+        if torn {
+            // Files opened for appending cannot be truncated on Windows.
+            OpenOptions::new()
+                .write(true)
+                .open(&self.path)?
+                .set_len(offset)?;
+        }
+        self.verified.store(offset, Ordering::Relaxed);
+        Ok(offset)
+    }
+
+    /// Opens a reader positioned at the first record, waiting for a shared
+    /// lock.
+    pub fn reader(&self) -> io::Result<LogReader> {
+        let guard = File::open(&self.path)?.lock_read()?;
+        Ok(LogReader {
+            reader: LockedBufReader::new(guard),
+            offset: 0,
+            failed: false,
+        })
+    }
+}
+
+impl LogReader {
+    /// Returns the offset of the next frame.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn read_record(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let record = read_frame(&mut self.reader, self.offset)?;
+        if let Some(record) = &record {
+            self.offset += (HEADER_LEN + record.len()) as u64;
+        }
+        Ok(record)
+    }
+}
+
+impl Iterator for LogReader {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let result = self.read_record();
+        self.failed = result.is_err();
+        result.transpose()
+    }
+}
+
+/// Reads the frame at `offset`, returning `None` at the end of the log.
+fn read_frame(reader: &mut impl Read, offset: u64) -> io::Result<Option<Vec<u8>>> {
+    let mut header = [0; HEADER_LEN];
+    let mut filled = 0;
+    while filled < HEADER_LEN {
+        match reader.read(&mut header[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => return Err(torn_frame(offset)),
+            Ok(n) => filled += n,
+            Err(error) if error.kind() == ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+    let [l0, l1, l2, l3, c0, c1, c2, c3] = header;
+    let len = u32::from_le_bytes([l0, l1, l2, l3]);
+    let checksum = u32::from_le_bytes([c0, c1, c2, c3]);
+
+    let mut record = Vec::new();
+    reader.take(u64::from(len)).read_to_end(&mut record)?;
+    if record.len() as u64 != u64::from(len) {
+        return Err(torn_frame(offset));
+    }
+    if crc32(&record) != checksum {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("checksum mismatch in frame at offset {offset}"),
+        ));
+    }
+    Ok(Some(record))
+}
+
+fn torn_frame(offset: u64) -> io::Error {
+    io::Error::new(
+        ErrorKind::UnexpectedEof,
+        format!("log ends inside the frame at offset {offset}"),
+    )
+}
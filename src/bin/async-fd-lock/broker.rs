@@ -0,0 +1,28 @@
+//! `async-fd-lock broker`: runs a lock broker.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use async_fd_lock::broker::{self, Broker};
+use clap::Args;
+
+/// Grant locks to clients over a Unix domain socket.
+#[derive(Debug, Args)]
+pub struct BrokerArgs {
+    /// The socket to listen on. Defaults to the socket used by the broker
+    /// strategy.
+    #[arg(long, value_name = "PATH")]
+    socket: Option<PathBuf>,
+}
+
+pub fn run(args: BrokerArgs) -> ExitCode {
+    let socket = args.socket.unwrap_or_else(broker::default_socket_path);
+    let result = Broker::bind(&socket).and_then(|broker| broker.run());
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("async-fd-lock: {}: {error}", socket.display());
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//! Runs a command while holding a lock on a file, like `flock(1)`, using the
+//! locking backends of `async-fd-lock`, inspects the locks held on files and
+//! runs lock brokers.
+
+#[cfg(unix)]
+mod broker;
+mod status;
+
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use async_fd_lock::blocking::{LockRead, LockWrite};
+use async_fd_lock::{LockStrategy, RwLockReadGuard, RwLockWriteGuard};
+use clap::{Args, Parser, Subcommand};
+
+/// Run a command while holding an advisory lock on a file.
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    subcommand: Option<Sub>,
+
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+#[derive(Debug, Subcommand)]
+enum Sub {
+    Status(status::StatusArgs),
+    #[cfg(unix)]
+    Broker(broker::BrokerArgs),
+}
+
+#[derive(Debug, Args)]
+struct RunArgs {
+    /// Take a shared lock.
+    #[arg(short, long, conflicts_with = "exclusive")]
+    shared: bool,
+
+    /// Take an exclusive lock. This is the default.
+    #[arg(short = 'x', long)]
+    exclusive: bool,
+
+    /// Fail instead of waiting if the lock is held by someone else.
+    #[arg(short, long, conflicts_with = "timeout")]
+    nonblock: bool,
+
+    /// Give up waiting for the lock after this many seconds.
+    #[arg(short = 'w', long, value_name = "SECONDS", value_parser = parse_timeout)]
+    timeout: Option<Duration>,
+
+    /// The exit code to use if the lock could not be acquired in time.
+    #[arg(short = 'E', long, value_name = "CODE", default_value_t = 1)]
+    conflict_exit_code: u8,
+
+    /// The locking mechanism: flock, fcntl, dotlock, broker or auto.
+    #[arg(
+        long,
+        value_name = "STRATEGY",
+        value_parser = parse_strategy,
+        default_value = "flock"
+    )]
+    strategy: LockStrategy,
+
+    /// The file to lock. It is created if it does not exist.
+    #[arg(required = true)]
+    file: Option<PathBuf>,
+
+    /// The command to run while holding the lock.
+    #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+    command: Vec<OsString>,
+}
+
+#[derive(Debug)]
+enum Guard {
+    Shared(RwLockReadGuard<File>),
+    Exclusive(RwLockWriteGuard<File>),
+}
+
+fn main() -> ExitCode {
+    match Cli::parse() {
+        Cli {
+            subcommand: Some(Sub::Status(args)),
+            ..
+        } => status::run(args),
+        #[cfg(unix)]
+        Cli {
+            subcommand: Some(Sub::Broker(args)),
+            ..
+        } => broker::run(args),
+        Cli { run, .. } => run_command(run),
+    }
+}
+
+fn run_command(cli: RunArgs) -> ExitCode {
+    let file = cli.file.clone().expect("required without a subcommand");
+    let guard = match lock(&file, &cli) {
+        Ok(guard) => guard,
+        Err(error) if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+            return ExitCode::from(cli.conflict_exit_code);
+        }
+        Err(error) => {
+            eprintln!("async-fd-lock: {}: {error}", file.display());
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let status = Command::new(&cli.command[0])
+        .args(&cli.command[1..])
+        .status();
+    if let Err(error) = guard.release() {
+        eprintln!("async-fd-lock: {}: {error}", file.display());
+    }
+    match status {
+        Ok(status) => ExitCode::from(exit_code(status)),
+        Err(error) => {
+            eprintln!(
+                "async-fd-lock: {}: {error}",
+                cli.command[0].to_string_lossy()
+            );
+            ExitCode::from(if error.kind() == ErrorKind::NotFound {
+                127
+            } else {
+                126
+            })
+        }
+    }
+}
+
+impl Guard {
+    fn release(self) -> io::Result<()> {
+        match self {
+            Self::Shared(guard) => guard.release().map(drop),
+            Self::Exclusive(guard) => guard.release().map(drop),
+        }
+    }
+}
+
+fn lock(path: &Path, cli: &RunArgs) -> io::Result<Guard> {
+    let file = open(path, cli.shared || cli.strategy == LockStrategy::Flock)?;
+    let (shared, strategy) = (cli.shared, cli.strategy);
+    let acquire = move |block: bool| -> io::Result<Guard> {
+        Ok(match (shared, block) {
+            (true, true) => Guard::Shared(file.lock_read_with(strategy)?),
+            (true, false) => Guard::Shared(file.try_lock_read_with(strategy)?),
+            (false, true) => Guard::Exclusive(file.lock_write_with(strategy)?),
+            (false, false) => Guard::Exclusive(file.try_lock_write_with(strategy)?),
+        })
+    };
+
+    match cli.timeout {
+        _ if cli.nonblock => acquire(false),
+        None => acquire(true),
+        Some(timeout) if timeout.is_zero() => acquire(false),
+        Some(timeout) => {
+            // The waiting thread is abandoned on timeout; exiting the process
+            // takes care of it.
+            let (send, recv) = mpsc::channel();
+            thread::spawn(move || {
+                let _ = send.send(acquire(true));
+            });
+            recv.recv_timeout(timeout)
+                .unwrap_or_else(|_| Err(ErrorKind::TimedOut.into()))
+        }
+    }
+}
+
+/// Opens `path` for locking, creating it if possible. Read-only files and
+/// directories are opened read-only if `read_only` is set, since only shared
+/// and `flock` locks can be taken on those.
+fn open(path: &Path, read_only: bool) -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .or_else(|error| match error.kind() {
+            ErrorKind::PermissionDenied | ErrorKind::IsADirectory if read_only => File::open(path),
+            ErrorKind::PermissionDenied | ErrorKind::IsADirectory => Err(io::Error::new(
+                error.kind(),
+                format!(
+                    "{error}; exclusive locks other than flock require write access, try --shared"
+                ),
+            )),
+            _ => Err(error),
+        })
+}
+
+fn exit_code(status: std::process::ExitStatus) -> u8 {
+    if let Some(code) = status.code() {
+        return code as u8;
+    }
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        return 128u8.wrapping_add(signal as u8);
+    }
+    1
+}
+
+fn parse_timeout(seconds: &str) -> Result<Duration, String> {
+    let seconds: f64 = seconds.parse().map_err(|error| format!("{error}"))?;
+    Duration::try_from_secs_f64(seconds).map_err(|error| format!("{error}"))
+}
+
+pub(crate) fn parse_strategy(strategy: &str) -> Result<LockStrategy, String> {
+    match strategy {
+        "flock" => Ok(LockStrategy::Flock),
+        "fcntl" => Ok(LockStrategy::Fcntl),
+        "dotlock" => Ok(LockStrategy::DotLock),
+        "broker" => Ok(LockStrategy::Broker),
+        "auto" => Ok(LockStrategy::Auto),
+        _ => Err("expected one of flock, fcntl, dotlock, broker or auto".into()),
+    }
+}
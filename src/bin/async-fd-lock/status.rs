@@ -0,0 +1,205 @@
+//! `async-fd-lock status`: reports the lock state of files.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use async_fd_lock::blocking::{LockRead, LockWrite};
+use async_fd_lock::LockStrategy;
+use clap::Args;
+use serde_json::{json, Value};
+
+/// Report whether files are read-locked, write-locked or free.
+#[derive(Debug, Args)]
+pub struct StatusArgs {
+    /// Print a JSON array with one object per file.
+    #[arg(long)]
+    json: bool,
+
+    /// The locking mechanism to probe with: flock, fcntl, dotlock, broker or
+    /// auto.
+    #[arg(
+        long,
+        value_name = "STRATEGY",
+        value_parser = crate::parse_strategy,
+        default_value = "flock"
+    )]
+    strategy: LockStrategy,
+
+    /// The files to inspect.
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Free,
+    ReadLocked,
+    WriteLocked,
+    /// Free or read-locked, for files that could only be probed with a
+    /// shared lock.
+    NotWriteLocked,
+    Missing,
+}
+
+/// A lock listed in `/proc/locks`.
+#[derive(Debug)]
+struct Holder {
+    kind: String,
+    mode: String,
+    pid: Option<u32>,
+}
+
+pub fn run(args: StatusArgs) -> ExitCode {
+    let mut failed = false;
+    let mut reports = Vec::new();
+    for path in &args.paths {
+        let report = match probe(path, args.strategy) {
+            Ok(state) => {
+                let holders = holders(path)
+                    .ok()
+                    .map(|holders| holders.iter().map(Holder::to_json).collect::<Vec<_>>());
+                json!({
+                    "path": path.display().to_string(),
+                    "state": state.as_str(),
+                    "holders": holders,
+                })
+            }
+            Err(error) => {
+                failed = true;
+                json!({ "path": path.display().to_string(), "error": error.to_string() })
+            }
+        };
+        if !args.json {
+            print_report(&report);
+        }
+        reports.push(report);
+    }
+    if args.json {
+        println!("{}", Value::Array(reports));
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+impl State {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Free => "free",
+            Self::ReadLocked => "read-locked",
+            Self::WriteLocked => "write-locked",
+            Self::NotWriteLocked => "not-write-locked",
+            Self::Missing => "missing",
+        }
+    }
+}
+
+impl Holder {
+    fn to_json(&self) -> Value {
+        json!({ "kind": self.kind, "mode": self.mode, "pid": self.pid })
+    }
+}
+
+/// Probes the state of the lock on `path` with non-blocking lock attempts.
+fn probe(path: &Path, strategy: LockStrategy) -> io::Result<State> {
+    let (file, writable) = match OpenOptions::new().read(true).write(true).open(path) {
+        Ok(file) => (file, true),
+        Err(error)
+            if matches!(
+                error.kind(),
+                ErrorKind::PermissionDenied | ErrorKind::IsADirectory
+            ) =>
+        {
+            (File::open(path)?, false)
+        }
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(State::Missing),
+        Err(error) => return Err(error),
+    };
+    // Exclusive locks other than `flock` cannot be taken on read-only files.
+    if !writable && strategy != LockStrategy::Flock {
+        return match file.try_lock_read_with(strategy) {
+            Ok(guard) => {
+                guard.release()?;
+                Ok(State::NotWriteLocked)
+            }
+            Err(error) if error.error.kind() == ErrorKind::WouldBlock => Ok(State::WriteLocked),
+            Err(error) => Err(error.error),
+        };
+    }
+    let file = match file.try_lock_write_with(strategy) {
+        Ok(guard) => {
+            guard.release()?;
+            return Ok(State::Free);
+        }
+        Err(error) if error.error.kind() == ErrorKind::WouldBlock => error.file,
+        Err(error) => return Err(error.error),
+    };
+    match file.try_lock_read_with(strategy) {
+        Ok(guard) => {
+            guard.release()?;
+            Ok(State::ReadLocked)
+        }
+        Err(error) if error.error.kind() == ErrorKind::WouldBlock => Ok(State::WriteLocked),
+        Err(error) => Err(error.error),
+    }
+}
+
+/// Lists the locks on `path` from `/proc/locks`.
+#[cfg(target_os = "linux")]
+fn holders(path: &Path) -> io::Result<Vec<Holder>> {
+    let stat = rustix::fs::stat(path)?;
+    let device = format!(
+        "{:02x}:{:02x}:{}",
+        rustix::fs::major(stat.st_dev),
+        rustix::fs::minor(stat.st_dev),
+        stat.st_ino
+    );
+    // Lines look like `1: FLOCK  ADVISORY  WRITE 1234 08:01:5678 0 EOF`, and
+    // blocked waiters are prefixed with `->`.
+    let locks = std::fs::read_to_string("/proc/locks")?;
+    Ok(locks
+        .lines()
+        .filter(|line| !line.contains("->"))
+        .filter_map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let [_, kind, _, mode, pid, id, ..] = fields[..] else {
+                return None;
+            };
+            (id == device).then(|| Holder {
+                kind: kind.to_owned(),
+                mode: mode.to_owned(),
+                // Open file description locks are not owned by a process.
+                pid: pid.parse().ok(),
+            })
+        })
+        .collect())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn holders(_path: &Path) -> io::Result<Vec<Holder>> {
+    Err(ErrorKind::Unsupported.into())
+}
+
+fn print_report(report: &Value) {
+    let path = report["path"].as_str().unwrap_or_default();
+    if let Some(error) = report["error"].as_str() {
+        eprintln!("async-fd-lock: {path}: {error}");
+        return;
+    }
+    println!("{path}: {}", report["state"].as_str().unwrap_or_default());
+    for holder in report["holders"].as_array().into_iter().flatten() {
+        let pid = match holder["pid"].as_u64() {
+            Some(pid) => format!("pid {pid}"),
+            None => "no owning process".into(),
+        };
+        println!(
+            "  {} {} ({pid})",
+            holder["kind"].as_str().unwrap_or_default(),
+            holder["mode"].as_str().unwrap_or_default()
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+use super::{check_peer, check_socket_dir, default_socket_path, Request};
+use crate::strategy::Held;
+use crate::sys::{path_of, AsOpenFile};
+use crate::{LockError, LockReadResult, LockWriteResult, RwLockReadGuard, RwLockWriteGuard};
+
+/// Locks files through a [`Broker`](super::Broker).
+#[derive(Debug, Clone)]
+pub struct Client {
+    socket: PathBuf,
+    name: Option<String>,
+}
+
+/// A lock granted by a broker, held for as long as the connection is open.
+#[derive(Debug)]
+pub(crate) struct Grant {
+    stream: UnixStream,
+}
+
+impl Client {
+    /// Creates a client of the broker listening on `socket`.
+    pub fn new(socket: impl Into<PathBuf>) -> Self {
+        Self {
+            socket: socket.into(),
+            name: None,
+        }
+    }
+
+    /// Creates a client of the broker listening on [`default_socket_path`].
+    pub fn from_env() -> Self {
+        Self::new(default_socket_path())
+    }
+
+    /// Locks `name` instead of the path of the file.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn socket(&self) -> &Path {
+        &self.socket
+    }
+
+    /// Takes a shared lock, waiting for exclusive holders to release it.
+    pub fn lock_read<T: AsOpenFile>(&self, file: T) -> LockReadResult<T> {
+        match self.acquire::<false, true, _>(&file) {
+            Ok(grant) => Ok(RwLockReadGuard::from_held(file, Held::broker(grant))),
+            Err(error) => Err(LockError::new(file, error)),
+        }
+    }
+
+    /// Takes a shared lock, failing with [`ErrorKind::WouldBlock`] if it is
+    /// held exclusively.
+    pub fn try_lock_read<T: AsOpenFile>(&self, file: T) -> LockReadResult<T> {
+        match self.acquire::<false, false, _>(&file) {
+            Ok(grant) => Ok(RwLockReadGuard::from_held(file, Held::broker(grant))),
+            Err(error) => Err(LockError::new(file, error)),
+        }
+    }
+
+    /// Takes an exclusive lock, waiting for other holders to release it.
+    pub fn lock_write<T: AsOpenFile>(&self, file: T) -> LockWriteResult<T> {
+        match self.acquire::<true, true, _>(&file) {
+            Ok(grant) => Ok(RwLockWriteGuard::from_held(file, Held::broker(grant))),
+            Err(error) => Err(LockError::new(file, error)),
+        }
+    }
+
+    /// Takes an exclusive lock, failing with [`ErrorKind::WouldBlock`] if it
+    /// is held.
+    pub fn try_lock_write<T: AsOpenFile>(&self, file: T) -> LockWriteResult<T> {
+        match self.acquire::<true, false, _>(&file) {
+            Ok(grant) => Ok(RwLockWriteGuard::from_held(file, Held::broker(grant))),
+            Err(error) => Err(LockError::new(file, error)),
+        }
+    }
+
+    /// Like [`Client::lock_read`], but waits on a blocking task.
+    #[cfg(feature = "async")]
+    pub async fn lock_read_async<T>(&self, file: T) -> LockReadResult<T>
+    where
+        T: AsOpenFile + Send + 'static,
+    {
+        let client = self.clone();
+        tokio::task::spawn_blocking(move || client.lock_read(file))
+            .await
+            .expect("the blocking task is not cancelable")
+    }
+
+    /// Like [`Client::lock_write`], but waits on a blocking task.
+    #[cfg(feature = "async")]
+    pub async fn lock_write_async<T>(&self, file: T) -> LockWriteResult<T>
+    where
+        T: AsOpenFile + Send + 'static,
+    {
+        let client = self.clone();
+        tokio::task::spawn_blocking(move || client.lock_write(file))
+            .await
+            .expect("the blocking task is not cancelable")
+    }
+
+    pub(crate) fn acquire<const WRITE: bool, const BLOCK: bool, T: AsOpenFile>(
+        &self,
+        file: &T,
+    ) -> io::Result<Grant> {
+        let name = match &self.name {
+            Some(name) => name.clone(),
+            None => path_of(file)?
+                .into_os_string()
+                .into_string()
+                .map_err(|_| io::Error::new(ErrorKind::InvalidInput, "path is not UTF-8"))?,
+        };
+        if name.is_empty() || name.contains('\n') {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "invalid lock name"));
+        }
+        let request = Request {
+            write: WRITE,
+            wait: BLOCK,
+            name,
+        };
+
+        check_socket_dir(&self.socket, false)?;
+        let stream = UnixStream::connect(&self.socket)?;
+        check_peer(&stream)?;
+        (&stream).write_all(request.to_string().as_bytes())?;
+        match read_line(&stream)?.as_str() {
+            "granted" => Ok(Grant { stream }),
+            "busy" => Err(ErrorKind::WouldBlock.into()),
+            response => Err(unexpected(response)),
+        }
+    }
+}
+
+impl Grant {
+    pub(crate) fn release(&self) -> io::Result<()> {
+        (&self.stream).write_all(b"release\n")?;
+        match read_line(&self.stream)?.as_str() {
+            "released" => Ok(()),
+            response => Err(unexpected(response)),
+        }
+    }
+}
+
+/// Reads a single line from the broker, without buffering past it.
+fn read_line(stream: &UnixStream) -> io::Result<String> {
+    let mut line = String::new();
+    // The broker sends nothing after a response until the next request.
+    BufReader::new(stream).read_line(&mut line)?;
+    if !line.ends_with('\n') {
+        return Err(ErrorKind::UnexpectedEof.into());
+    }
+    line.pop();
+    Ok(line)
+}
+
+fn unexpected(response: &str) -> io::Error {
+    match response.strip_prefix("error ") {
+        Some(message) => io::Error::other(format!("broker: {message}")),
+        None => io::Error::new(
+            ErrorKind::InvalidData,
+            format!("unexpected broker response {response:?}"),
+        ),
+    }
+}
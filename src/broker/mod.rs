@@ -0,0 +1,170 @@
+//! Named locks granted by a local daemon, for filesystems where kernel locks
+//! are unreliable.
+//!
+//! A [`Broker`] listens on a Unix domain socket and grants read and write
+//! locks on names. Every lock is held over its own connection, so a lock
+//! whose client crashes or disconnects expires as soon as the broker notices
+//! the connection closing. [`Client`] locks files through a broker and returns
+//! the usual guards. By default, the name of the lock is the path of the file,
+//! so all processes on a host that share a broker exclude each other, no
+//! matter which filesystem the file resides on.
+//!
+//! The guards can also be taken with [`LockStrategy::Broker`](crate::LockStrategy::Broker),
+//! which uses the broker at [`default_socket_path`]. The `async-fd-lock
+//! broker` command runs a broker.
+//!
+//! On Linux, the broker only serves clients running as the same user as
+//! itself or as root, and clients only trust such a broker.
+//!
+//! The protocol is line based. A client sends `lock <read|write> <wait|try>
+//! <name>`, and the broker answers `granted`, `busy` or `error <message>`.
+//! Once granted, the client sends `release` and the broker answers
+//! `released`.
+//!
+//! # Example
+//!
+//! ```
+//! use std::io::ErrorKind;
+//! use async_fd_lock::broker::{Broker, Client};
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let broker = Broker::bind(dir.path().join("broker.sock"))?;
+//! let client = Client::new(broker.path());
+//! std::thread::spawn(move || broker.run());
+//!
+//! let path = dir.path().join("foo.txt");
+//! let guard = client.lock_write(std::fs::File::create(&path)?)?;
+//! let err = client.try_lock_read(std::fs::File::open(&path)?).unwrap_err();
+//! assert_eq!(err.error.kind(), ErrorKind::WouldBlock);
+//! guard.release()?;
+//! # std::io::Result::Ok(())
+//! ```
+
+mod client;
+mod server;
+
+use std::env;
+use std::fs::{self, DirBuilder};
+use std::io::{self, ErrorKind};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+pub use client::Client;
+pub(crate) use client::Grant;
+pub use server::Broker;
+
+/// The environment variable that overrides [`default_socket_path`].
+pub const SOCKET_ENV: &str = "ASYNC_FD_LOCK_BROKER";
+
+/// A request to lock a name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Request {
+    write: bool,
+    wait: bool,
+    name: String,
+}
+
+/// Returns the socket of the broker used by
+/// [`LockStrategy::Broker`](crate::LockStrategy::Broker).
+///
+/// This is the value of the `ASYNC_FD_LOCK_BROKER` environment variable if it
+/// is set, `$XDG_RUNTIME_DIR/async-fd-lock.sock` if that is set, and
+/// `async-fd-lock-<uid>/broker.sock` in the temporary directory otherwise.
+/// That directory is created by the broker with mode 0700, and neither the
+/// broker nor its clients use it unless it is private to the current user.
+pub fn default_socket_path() -> PathBuf {
+    if let Some(path) = env::var_os(SOCKET_ENV) {
+        return path.into();
+    }
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("async-fd-lock.sock"),
+        None => private_dir().join("broker.sock"),
+    }
+}
+
+/// The per-user directory in the shared temporary directory.
+fn private_dir() -> PathBuf {
+    env::temp_dir().join(format!(
+        "async-fd-lock-{}",
+        rustix::process::getuid().as_raw()
+    ))
+}
+
+/// Checks that `socket` is not in a directory that others could have placed
+/// it in, creating the directory if `create` is set.
+fn check_socket_dir(socket: &Path, create: bool) -> io::Result<()> {
+    let dir = private_dir();
+    if socket.parent() != Some(&dir) {
+        return Ok(());
+    }
+    if create {
+        match DirBuilder::new().mode(0o700).create(&dir) {
+            Err(error) if error.kind() == ErrorKind::AlreadyExists => {}
+            result => result?,
+        }
+    }
+    let metadata = fs::symlink_metadata(&dir)?;
+    let private = metadata.is_dir()
+        && metadata.uid() == rustix::process::getuid().as_raw()
+        && metadata.mode() & 0o077 == 0;
+    if !private {
+        return Err(io::Error::new(
+            ErrorKind::PermissionDenied,
+            format!("{} is not private to the current user", dir.display()),
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that the other end of `stream` runs as the current user or as root.
+fn check_peer(stream: &UnixStream) -> io::Result<()> {
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_os = "linux", target_os = "android"))] {
+            let peer = rustix::net::sockopt::get_socket_peercred(stream)?.uid;
+            if !peer.is_root() && peer != rustix::process::getuid() {
+                return Err(io::Error::new(
+                    ErrorKind::PermissionDenied,
+                    "the broker connection belongs to another user",
+                ));
+            }
+            Ok(())
+        } else {
+            let _ = stream;
+            Ok(())
+        }
+    }
+}
+
+impl Request {
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(4, ' ');
+        if fields.next()? != "lock" {
+            return None;
+        }
+        let write = match fields.next()? {
+            "read" => false,
+            "write" => true,
+            _ => return None,
+        };
+        let wait = match fields.next()? {
+            "wait" => true,
+            "try" => false,
+            _ => return None,
+        };
+        let name = fields.next().filter(|name| !name.is_empty())?;
+        Some(Self {
+            write,
+            wait,
+            name: name.to_owned(),
+        })
+    }
+}
+
+impl std::fmt::Display for Request {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mode = if self.write { "write" } else { "read" };
+        let wait = if self.wait { "wait" } else { "try" };
+        writeln!(f, "lock {mode} {wait} {}", self.name)
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+use super::{check_peer, check_socket_dir, Request};
+
+/// A lock broker listening on a Unix domain socket.
+///
+/// The socket file is removed when the broker is dropped.
+#[derive(Debug)]
+pub struct Broker {
+    listener: UnixListener,
+    path: PathBuf,
+    state: Arc<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    locks: Mutex<HashMap<String, Entry>>,
+    changed: Condvar,
+}
+
+/// The holders of a name, and the writers waiting for it.
+#[derive(Debug, Default)]
+struct Entry {
+    readers: usize,
+    writer: bool,
+    waiting_writers: usize,
+}
+
+/// A lock granted to a connection, released on drop.
+struct Granted<'a> {
+    state: &'a State,
+    request: &'a Request,
+}
+
+impl Broker {
+    /// Listens on the socket at `path`.
+    ///
+    /// A socket file left behind by a broker that is no longer running is
+    /// replaced. Fails with [`ErrorKind::AddrInUse`] if another broker is
+    /// listening on it.
+    pub fn bind(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        check_socket_dir(&path, true)?;
+        let listener = match UnixListener::bind(&path) {
+            Err(error) if error.kind() == ErrorKind::AddrInUse => {
+                if UnixStream::connect(&path).is_ok() {
+                    return Err(error);
+                }
+                fs::remove_file(&path)?;
+                UnixListener::bind(&path)?
+            }
+            result => result?,
+        };
+        Ok(Self {
+            listener,
+            path,
+            state: Arc::default(),
+        })
+    }
+
+    /// Returns the path of the socket.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Serves clients, each on its own thread, until accepting a connection
+    /// fails.
+    pub fn run(&self) -> io::Result<()> {
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            };
+            if check_peer(&stream).is_err() {
+                let _ = (&stream).write_all(b"error permission denied\n");
+                continue;
+            }
+            let state = self.state.clone();
+            thread::spawn(move || {
+                // Errors only affect the connection, whose lock is released.
+                let _ = state.serve(&stream);
+            });
+        }
+    }
+}
+
+impl Drop for Broker {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl State {
+    /// Serves a single lock over `stream`.
+    fn serve(&self, stream: &UnixStream) -> io::Result<()> {
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let Some(request) = Request::parse(line.trim_end_matches('\n')) else {
+            return (&*stream).write_all(b"error malformed request\n");
+        };
+        if !self.acquire(&request) {
+            return (&*stream).write_all(b"busy\n");
+        }
+        let granted = Granted {
+            state: self,
+            request: &request,
+        };
+        (&*stream).write_all(b"granted\n")?;
+
+        // The lock expires when the client disconnects without releasing it.
+        line.clear();
+        reader.read_line(&mut line)?;
+        drop(granted);
+        if line == "release\n" {
+            (&*stream).write_all(b"released\n")?;
+        }
+        Ok(())
+    }
+
+    fn acquire(&self, request: &Request) -> bool {
+        let mut locks = self.locks();
+        let entry = locks.entry(request.name.clone()).or_default();
+        if !request.wait {
+            return entry.try_acquire(request.write);
+        }
+        if request.write {
+            entry.waiting_writers += 1;
+        }
+        loop {
+            let entry = locks.entry(request.name.clone()).or_default();
+            if entry.try_acquire(request.write) {
+                if request.write {
+                    entry.waiting_writers -= 1;
+                }
+                return true;
+            }
+            locks = self
+                .changed
+                .wait(locks)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    fn release(&self, request: &Request) {
+        let mut locks = self.locks();
+        let entry = locks
+            .get_mut(&request.name)
+            .expect("granted locks have an entry");
+        if request.write {
+            entry.writer = false;
+        } else {
+            entry.readers -= 1;
+        }
+        if !entry.writer && entry.readers == 0 && entry.waiting_writers == 0 {
+            locks.remove(&request.name);
+        }
+        self.changed.notify_all();
+    }
+
+    fn locks(&self) -> MutexGuard<'_, HashMap<String, Entry>> {
+        self.locks.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Entry {
+    fn try_acquire(&mut self, write: bool) -> bool {
+        if write {
+            if self.writer || self.readers > 0 {
+                return false;
+            }
+            self.writer = true;
+        } else {
+            // Readers queue behind waiting writers, so writers do not starve.
+            if self.writer || self.waiting_writers > 0 {
+                return false;
+            }
+            self.readers += 1;
+        }
+        true
+    }
+}
+
+impl Drop for Granted<'_> {
+    fn drop(&mut self) {
+        self.state.release(self.request);
+    }
+}
@@ -0,0 +1,168 @@
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, Write};
+
+use super::forward_as_open_file;
+use crate::{AsOpenFile, LockStrategy, RwLockReadGuard, RwLockWriteGuard};
+
+/// A [`BufWriter`] around an exclusively locked file.
+///
+/// The buffer is flushed before the lock is released, whether through
+/// [`LockedBufWriter::release`] or on drop. Errors while flushing on drop are
+/// ignored, so prefer `release` when they matter.
+#[must_use = "if unused the RwLock will immediately unlock"]
+#[derive(Debug)]
+pub struct LockedBufWriter<T: AsOpenFile + Write> {
+    inner: BufWriter<RwLockWriteGuard<T>>,
+}
+
+/// A [`BufReader`] around a shared locked file.
+#[must_use = "if unused the RwLock will immediately unlock"]
+#[derive(Debug)]
+pub struct LockedBufReader<T: AsOpenFile + Read> {
+    inner: BufReader<RwLockReadGuard<T>>,
+}
+
+impl<T: AsOpenFile + Write> LockedBufWriter<T> {
+    pub fn new(guard: RwLockWriteGuard<T>) -> Self {
+        Self {
+            inner: BufWriter::new(guard),
+        }
+    }
+
+    pub fn with_capacity(capacity: usize, guard: RwLockWriteGuard<T>) -> Self {
+        Self {
+            inner: BufWriter::with_capacity(capacity, guard),
+        }
+    }
+
+    /// Returns the mechanism used to hold the lock.
+    pub fn strategy(&self) -> LockStrategy {
+        self.inner.get_ref().strategy()
+    }
+
+    pub fn get_ref(&self) -> &T {
+        self.inner.get_ref().inner()
+    }
+
+    /// Returns the data that has not been written to the file yet.
+    pub fn buffer(&self) -> &[u8] {
+        self.inner.buffer()
+    }
+
+    /// Flushes the buffer and returns the guard, keeping the lock held.
+    pub fn into_guard(self) -> io::Result<RwLockWriteGuard<T>> {
+        self.inner
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)
+    }
+
+    /// Flushes the buffer and releases the lock, returning the inner file.
+    ///
+    /// The lock is released even if flushing fails.
+    pub fn release(self) -> io::Result<T> {
+        self.into_guard()?.release()
+    }
+}
+
+impl<T: AsOpenFile + Read> LockedBufReader<T> {
+    pub fn new(guard: RwLockReadGuard<T>) -> Self {
+        Self {
+            inner: BufReader::new(guard),
+        }
+    }
+
+    pub fn with_capacity(capacity: usize, guard: RwLockReadGuard<T>) -> Self {
+        Self {
+            inner: BufReader::with_capacity(capacity, guard),
+        }
+    }
+
+    /// Returns the mechanism used to hold the lock.
+    pub fn strategy(&self) -> LockStrategy {
+        self.inner.get_ref().strategy()
+    }
+
+    pub fn get_ref(&self) -> &T {
+        self.inner.get_ref().inner()
+    }
+
+    /// Returns the data that has been read from the file but not consumed yet.
+    pub fn buffer(&self) -> &[u8] {
+        self.inner.buffer()
+    }
+
+    /// Returns the guard, keeping the lock held. Buffered data is discarded.
+    pub fn into_guard(self) -> RwLockReadGuard<T> {
+        self.inner.into_inner()
+    }
+
+    /// Releases the lock, returning the inner file. Buffered data is
+    /// discarded.
+    pub fn release(self) -> io::Result<T> {
+        self.into_guard().release()
+    }
+}
+
+forward_as_open_file!(LockedBufWriter, T: AsOpenFile + Write);
+forward_as_open_file!(LockedBufReader, T: AsOpenFile + Read);
+
+/// Delegate [`Write`] to the buffer.
+impl<T: AsOpenFile + Write> Write for LockedBufWriter<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        self.inner.write_vectored(bufs)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.inner.write_all(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Seeking flushes the buffer first.
+impl<T: AsOpenFile + Write + Seek> Seek for LockedBufWriter<T> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+/// Delegate [`Read`] to the buffer.
+impl<T: AsOpenFile + Read> Read for LockedBufReader<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut<'_>]) -> io::Result<usize> {
+        self.inner.read_vectored(bufs)
+    }
+
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        self.inner.read_to_end(buf)
+    }
+
+    fn read_to_string(&mut self, buf: &mut String) -> io::Result<usize> {
+        self.inner.read_to_string(buf)
+    }
+}
+
+impl<T: AsOpenFile + Read> BufRead for LockedBufReader<T> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt)
+    }
+}
+
+/// Seeking discards the buffer.
+impl<T: AsOpenFile + Read + Seek> Seek for LockedBufReader<T> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
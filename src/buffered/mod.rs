@@ -0,0 +1,29 @@
+//! Buffered wrappers around guards that never release the lock with data
+//! still sitting in a buffer.
+
+pub(crate) mod blocking;
+#[cfg(feature = "async")]
+pub(crate) mod nonblocking;
+
+/// Forwards `AsOpenFile` to the file returned by `get_ref`.
+macro_rules! forward_as_open_file {
+    ($name:ident, $T:ident: $($bound:tt)+) => {
+        cfg_if::cfg_if! {
+            if #[cfg(unix)] {
+                impl<$T: $($bound)+> std::os::fd::AsFd for $name<$T> {
+                    fn as_fd(&self) -> std::os::fd::BorrowedFd<'_> {
+                        self.get_ref().as_fd()
+                    }
+                }
+            } else if #[cfg(windows)] {
+                impl<$T: $($bound)+> std::os::windows::io::AsHandle for $name<$T> {
+                    fn as_handle(&self) -> std::os::windows::io::BorrowedHandle<'_> {
+                        self.get_ref().as_handle()
+                    }
+                }
+            }
+        }
+    };
+}
+
+pub(crate) use forward_as_open_file;
@@ -0,0 +1,245 @@
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{
+    AsyncBufRead, AsyncRead, AsyncSeek, AsyncWrite, AsyncWriteExt, BufReader, BufWriter, ReadBuf,
+};
+
+use super::forward_as_open_file;
+use crate::sys::duplicate;
+use crate::{AsOpenFile, LockStrategy, RwLockReadGuard, RwLockWriteGuard};
+
+/// A [`BufWriter`] around an exclusively locked file.
+///
+/// The buffer is flushed before the lock is released. Call
+/// [`LockedBufWriter::release`] to flush asynchronously and observe errors.
+/// Data still buffered when the writer is dropped, for example because the
+/// task was cancelled, is written synchronously through a duplicate of the
+/// file's handle, and errors are ignored. Writes that the inner file itself
+/// still has in flight, like those of `tokio::fs::File`, are not waited for.
+#[must_use = "if unused the RwLock will immediately unlock"]
+#[derive(Debug)]
+pub struct LockedBufWriter<T>
+where
+    T: AsOpenFile + AsyncWrite + Unpin + Send + 'static,
+{
+    // Only taken on release or drop.
+    inner: Option<BufWriter<RwLockWriteGuard<T>>>,
+}
+
+/// A [`BufReader`] around a shared locked file.
+#[must_use = "if unused the RwLock will immediately unlock"]
+#[derive(Debug)]
+pub struct LockedBufReader<T: AsOpenFile + AsyncRead + Unpin> {
+    inner: BufReader<RwLockReadGuard<T>>,
+}
+
+impl<T> LockedBufWriter<T>
+where
+    T: AsOpenFile + AsyncWrite + Unpin + Send + 'static,
+{
+    pub fn new(guard: RwLockWriteGuard<T>) -> Self {
+        Self {
+            inner: Some(BufWriter::new(guard)),
+        }
+    }
+
+    pub fn with_capacity(capacity: usize, guard: RwLockWriteGuard<T>) -> Self {
+        Self {
+            inner: Some(BufWriter::with_capacity(capacity, guard)),
+        }
+    }
+
+    fn writer(&self) -> &BufWriter<RwLockWriteGuard<T>> {
+        self.inner
+            .as_ref()
+            .expect("writer only removed during release")
+    }
+
+    fn writer_mut(&mut self) -> Pin<&mut BufWriter<RwLockWriteGuard<T>>> {
+        Pin::new(
+            self.inner
+                .as_mut()
+                .expect("writer only removed during release"),
+        )
+    }
+
+    /// Returns the mechanism used to hold the lock.
+    pub fn strategy(&self) -> LockStrategy {
+        self.writer().get_ref().strategy()
+    }
+
+    pub fn get_ref(&self) -> &T {
+        self.writer().get_ref().inner()
+    }
+
+    /// Returns the data that has not been written to the file yet.
+    pub fn buffer(&self) -> &[u8] {
+        self.writer().buffer()
+    }
+
+    /// Flushes the buffer and returns the guard, keeping the lock held.
+    pub async fn into_guard(mut self) -> io::Result<RwLockWriteGuard<T>> {
+        if let Err(error) = self.flush().await {
+            // Release the lock right away instead of retrying on drop.
+            self.inner = None;
+            return Err(error);
+        }
+        let writer = self
+            .inner
+            .take()
+            .expect("writer only removed during release");
+        Ok(writer.into_inner())
+    }
+
+    /// Flushes the buffer and releases the lock, returning the inner file.
+    ///
+    /// If flushing fails, the buffered data is discarded and the lock is
+    /// released anyway.
+    pub async fn release(self) -> io::Result<T> {
+        self.into_guard().await?.release()
+    }
+}
+
+impl<T> Drop for LockedBufWriter<T>
+where
+    T: AsOpenFile + AsyncWrite + Unpin + Send + 'static,
+{
+    fn drop(&mut self) {
+        let Some(writer) = self.inner.take() else {
+            return;
+        };
+        if writer.buffer().is_empty() {
+            return;
+        }
+        // Flushing the writer needs a runtime, so write through a duplicate,
+        // which shares the position of the file. The lock is released when
+        // the writer is dropped afterwards.
+        if let Ok(mut file) = duplicate(writer.get_ref().inner()) {
+            let _ = file.write_all(writer.buffer());
+        }
+    }
+}
+
+impl<T: AsOpenFile + AsyncRead + Unpin> LockedBufReader<T> {
+    pub fn new(guard: RwLockReadGuard<T>) -> Self {
+        Self {
+            inner: BufReader::new(guard),
+        }
+    }
+
+    pub fn with_capacity(capacity: usize, guard: RwLockReadGuard<T>) -> Self {
+        Self {
+            inner: BufReader::with_capacity(capacity, guard),
+        }
+    }
+
+    /// Returns the mechanism used to hold the lock.
+    pub fn strategy(&self) -> LockStrategy {
+        self.inner.get_ref().strategy()
+    }
+
+    pub fn get_ref(&self) -> &T {
+        self.inner.get_ref().inner()
+    }
+
+    /// Returns the data that has been read from the file but not consumed yet.
+    pub fn buffer(&self) -> &[u8] {
+        self.inner.buffer()
+    }
+
+    /// Returns the guard, keeping the lock held. Buffered data is discarded.
+    pub fn into_guard(self) -> RwLockReadGuard<T> {
+        self.inner.into_inner()
+    }
+
+    /// Releases the lock, returning the inner file. Buffered data is
+    /// discarded.
+    pub fn release(self) -> io::Result<T> {
+        self.into_guard().release()
+    }
+}
+
+forward_as_open_file!(LockedBufWriter, T: AsOpenFile + AsyncWrite + Unpin + Send + 'static);
+forward_as_open_file!(LockedBufReader, T: AsOpenFile + AsyncRead + Unpin);
+
+/// Delegate [`AsyncWrite`] to the buffer.
+impl<T> AsyncWrite for LockedBufWriter<T>
+where
+    T: AsOpenFile + AsyncWrite + Unpin + Send + 'static,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.writer_mut().poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.writer_mut().poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.writer().is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.writer_mut().poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.writer_mut().poll_shutdown(cx)
+    }
+}
+
+/// Seeking flushes the buffer first.
+impl<T> AsyncSeek for LockedBufWriter<T>
+where
+    T: AsOpenFile + AsyncWrite + AsyncSeek + Unpin + Send + 'static,
+{
+    fn start_seek(mut self: Pin<&mut Self>, position: io::SeekFrom) -> io::Result<()> {
+        self.writer_mut().start_seek(position)
+    }
+
+    fn poll_complete(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        self.writer_mut().poll_complete(cx)
+    }
+}
+
+/// Delegate [`AsyncRead`] to the buffer.
+impl<T: AsOpenFile + AsyncRead + Unpin> AsyncRead for LockedBufReader<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<T: AsOpenFile + AsyncRead + Unpin> AsyncBufRead for LockedBufReader<T> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        Pin::new(&mut self.get_mut().inner).poll_fill_buf(cx)
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        Pin::new(&mut self.inner).consume(amt)
+    }
+}
+
+/// Seeking discards the buffer.
+impl<T: AsOpenFile + AsyncRead + AsyncSeek + Unpin> AsyncSeek for LockedBufReader<T> {
+    fn start_seek(mut self: Pin<&mut Self>, position: io::SeekFrom) -> io::Result<()> {
+        Pin::new(&mut self.inner).start_seek(position)
+    }
+
+    fn poll_complete(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Pin::new(&mut self.inner).poll_complete(cx)
+    }
+}
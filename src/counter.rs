@@ -0,0 +1,159 @@
+//! Monotonically increasing counters shared between processes.
+//!
+//! A [`FileCounter`] file holds two slots, each storing the counter as a
+//! little-endian `u64` followed by its CRC-32 checksum. Every update
+//! overwrites the slot holding the older value, so a write torn by a crash
+//! leaves the other slot intact, and the counter falls back to its previous
+//! value. The slot holding the larger valid value is the current one.
+//!
+//! # Example
+//!
+//! ```
+//! use async_fd_lock::counter::FileCounter;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let counter = FileCounter::open(dir.path().join("ids"))?;
+//! assert_eq!(counter.increment()?, 1);
+//! assert_eq!(counter.reserve(10)?, 2..12);
+//! assert_eq!(counter.get()?, 11);
+//! # std::io::Result::Ok(())
+//! ```
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::blocking::{LockRead, LockWrite};
+use crate::checksum::crc32;
+
+const SLOT_LEN: usize = 12;
+
+/// A `u64` counter stored in a file.
+///
+/// Every operation locks the file through a handle of its own, so threads
+/// and tasks sharing a counter exclude each other like processes do.
+#[derive(Debug, Clone)]
+pub struct FileCounter {
+    path: PathBuf,
+}
+
+impl FileCounter {
+    /// Opens the counter at `path`, creating it with a value of zero if it
+    /// does not exist.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let counter = Self {
+            path: path.as_ref().to_owned(),
+        };
+        counter.open_file()?;
+        Ok(counter)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the current value under a shared lock.
+    pub fn get(&self) -> io::Result<u64> {
+        let mut guard = self.open_file()?.lock_read()?;
+        let (value, _) = read_slots(&mut guard)?;
+        guard.release()?;
+        Ok(value)
+    }
+
+    /// Increments the counter under an exclusive lock, returning the new
+    /// value.
+    pub fn increment(&self) -> io::Result<u64> {
+        self.reserve(1).map(|range| range.start)
+    }
+
+    /// Advances the counter by `count` under an exclusive lock, returning the
+    /// values reserved for the caller.
+    ///
+    /// Fails with [`ErrorKind::InvalidInput`] if the counter would reach
+    /// `u64::MAX`.
+    pub fn reserve(&self, count: u64) -> io::Result<Range<u64>> {
+        let mut guard = self.open_file()?.lock_write()?;
+        let (value, stale_slot) = read_slots(&mut guard)?;
+        let next = value
+            .checked_add(count)
+            .filter(|&next| next < u64::MAX)
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "counter overflow"))?;
+
+        let mut slot = [0; SLOT_LEN];
+        slot[..8].copy_from_slice(&next.to_le_bytes());
+        slot[8..].copy_from_slice(&crc32(&next.to_le_bytes()).to_le_bytes());
+        guard.seek(SeekFrom::Start((stale_slot * SLOT_LEN) as u64))?;
+        guard.write_all(&slot)?;
+        guard.inner().sync_data()?;
+        guard.release()?;
+        Ok(value + 1..next + 1)
+    }
+
+    /// Like [`FileCounter::get`], but waits on a blocking task.
+    #[cfg(feature = "async")]
+    pub async fn get_async(&self) -> io::Result<u64> {
+        let counter = self.clone();
+        tokio::task::spawn_blocking(move || counter.get())
+            .await
+            .expect("the blocking task is not cancelable")
+    }
+
+    /// Like [`FileCounter::increment`], but waits on a blocking task.
+    #[cfg(feature = "async")]
+    pub async fn increment_async(&self) -> io::Result<u64> {
+        self.reserve_async(1).await.map(|range| range.start)
+    }
+
+    /// Like [`FileCounter::reserve`], but waits on a blocking task.
+    #[cfg(feature = "async")]
+    pub async fn reserve_async(&self, count: u64) -> io::Result<Range<u64>> {
+        let counter = self.clone();
+        tokio::task::spawn_blocking(move || counter.reserve(count))
+            .await
+            .expect("the blocking task is not cancelable")
+    }
+
+    /// Opens a handle of the file of its own, which `flock` locks
+    /// independently of the others.
+    fn open_file(&self) -> io::Result<File> {
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.path)
+    }
+}
+
+/// Returns the current value and the index of the slot to overwrite next.
+fn read_slots(mut file: impl Read + Seek) -> io::Result<(u64, usize)> {
+    let mut bytes = Vec::with_capacity(2 * SLOT_LEN);
+    file.seek(SeekFrom::Start(0))?;
+    file.take(2 * SLOT_LEN as u64).read_to_end(&mut bytes)?;
+    if bytes.is_empty() {
+        return Ok((0, 0));
+    }
+
+    let slots = [0, 1].map(|index| {
+        if bytes.len() <= index * SLOT_LEN {
+            // Never written, so it implicitly holds the initial value.
+            return Some(0);
+        }
+        let slot = bytes.get(index * SLOT_LEN..(index + 1) * SLOT_LEN)?;
+        let (value, checksum) = slot.split_at(8);
+        let checksum = u32::from_le_bytes(checksum.try_into().expect("slot is 12 bytes"));
+        (crc32(value) == checksum)
+            .then(|| u64::from_le_bytes(value.try_into().expect("value is 8 bytes")))
+    });
+    match slots {
+        [Some(first), Some(second)] if first >= second => Ok((first, 1)),
+        [Some(_), Some(second)] => Ok((second, 0)),
+        [Some(first), None] => Ok((first, 1)),
+        [None, Some(second)] => Ok((second, 0)),
+        [None, None] => Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "both counter slots are corrupted",
+        )),
+    }
+}
@@ -0,0 +1,307 @@
+//! Detection of deadlocks between owners in this process.
+//!
+//! Once enabled with [`set_detection`], every lock acquired through this crate
+//! is recorded in a wait-for graph, along with the [`Owner`] that holds it and
+//! the lock each owner is blocked on. A blocking acquisition that would wait
+//! for a lock held, directly or through a chain of waiting owners, by the
+//! acquiring owner itself can never succeed. Depending on the [`Detection`]
+//! mode, it fails with [`ErrorKind::Deadlock`] carrying a [`DeadlockReport`],
+//! or the report is passed to the hook set with [`set_report_hook`] before
+//! waiting anyway.
+//!
+//! Detection is meant for debugging, and only knows about locks acquired
+//! while it is enabled. Owners are threads, or
+//! [`scope`](crate::reentrant::scope)s in async code. Locks acquired
+//! asynchronously outside of a scope are not recorded, since the futures of a
+//! task or a thread blocking on them do not wait for each other in the way
+//! threads do. Acquiring a conflicting lock on a file the owner already holds
+//! counts as a deadlock, even through a handle sharing the held lock.
+//!
+//! A lock stays recorded as held by the owner that acquired it, even after its
+//! guard was passed to another owner, such as a thread it was sent to. Do not
+//! pass guards between owners while detection is enabled: if the owner that
+//! acquired a lock waits for the same file after handing the guard over, it is
+//! reported as waiting for itself.
+//!
+//! # Example
+//!
+//! ```
+//! use std::io::ErrorKind;
+//! use async_fd_lock::blocking::{LockRead, LockWrite};
+//! use async_fd_lock::deadlock::{self, DeadlockReport, Detection};
+//!
+//! deadlock::set_detection(Detection::Error);
+//! let dir = tempfile::tempdir().unwrap();
+//! let path = dir.path().join("foo.txt");
+//!
+//! let _guard = std::fs::File::create(&path)?.lock_read()?;
+//! let err = std::fs::File::create(&path)?.lock_write().unwrap_err();
+//! assert_eq!(err.error.kind(), ErrorKind::Deadlock);
+//! let report = err.error.get_ref().unwrap().downcast_ref::<DeadlockReport>().unwrap();
+//! assert_eq!(report.cycle().len(), 1);
+//! # std::io::Result::Ok(())
+//! ```
+
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::reentrant::Owner;
+use crate::sys::{file_id, path_of, AsOpenFile, FileId};
+
+static DETECTION: AtomicU8 = AtomicU8::new(Detection::Off as u8);
+
+static GRAPH: Mutex<Graph> = Mutex::new(Graph {
+    next: 0,
+    holds: Vec::new(),
+    waits: Vec::new(),
+});
+
+static HOOK: Mutex<Option<Arc<ReportHook>>> = Mutex::new(None);
+
+type ReportHook = dyn Fn(&DeadlockReport) + Send + Sync;
+
+/// What to do about acquisitions that would deadlock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Detection {
+    /// Do not record locks. This is the default.
+    Off,
+    /// Pass a [`DeadlockReport`] to the hook set with [`set_report_hook`],
+    /// then wait anyway.
+    Report,
+    /// Fail with [`ErrorKind::Deadlock`].
+    Error,
+}
+
+/// A cycle of owners that wait for each other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeadlockReport {
+    cycle: Vec<Wait>,
+}
+
+/// An owner waiting for a lock in a [`DeadlockReport`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Wait {
+    /// The waiting owner.
+    pub owner: Owner,
+    /// The path of the file, if it could be determined.
+    pub path: Option<PathBuf>,
+    /// Whether the owner waits for an exclusive lock.
+    pub write: bool,
+}
+
+/// A lock recorded in the graph, removed on drop.
+#[derive(Debug)]
+pub(crate) struct Hold {
+    serial: u64,
+}
+
+/// An acquisition recorded in the graph, removed on drop.
+#[derive(Debug)]
+pub(crate) struct Acquisition {
+    serial: u64,
+    id: FileId,
+    owner: Owner,
+    write: bool,
+    path: Option<PathBuf>,
+}
+
+struct Graph {
+    next: u64,
+    holds: Vec<Edge>,
+    waits: Vec<Edge>,
+}
+
+/// An owner holding or waiting for a lock on a file.
+struct Edge {
+    serial: u64,
+    id: FileId,
+    owner: Owner,
+    write: bool,
+    path: Option<PathBuf>,
+}
+
+/// Sets what to do about acquisitions that would deadlock.
+pub fn set_detection(detection: Detection) {
+    DETECTION.store(detection as u8, Ordering::Relaxed);
+}
+
+/// Sets the function that receives the reports of [`Detection::Report`],
+/// replacing the previous one. Reports are discarded until a hook is set.
+/// The hook is called by the owner about to wait.
+pub fn set_report_hook(hook: impl Fn(&DeadlockReport) + Send + Sync + 'static) {
+    *HOOK.lock().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(hook));
+}
+
+/// Returns what is done about acquisitions that would deadlock.
+pub fn detection() -> Detection {
+    match DETECTION.load(Ordering::Relaxed) {
+        0 => Detection::Off,
+        1 => Detection::Report,
+        _ => Detection::Error,
+    }
+}
+
+impl DeadlockReport {
+    /// Returns the waits that form the cycle, starting with the acquisition
+    /// that would close it. Each owner waits for a lock held by the next one,
+    /// and the last one for a lock held by the first one.
+    pub fn cycle(&self) -> &[Wait] {
+        &self.cycle
+    }
+}
+
+impl fmt::Display for DeadlockReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "deadlock: ")?;
+        for (index, wait) in self.cycle.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", held by ")?;
+            }
+            let mode = if wait.write { "exclusive" } else { "shared" };
+            match &wait.path {
+                Some(path) => write!(
+                    f,
+                    "{:?} waits for a {mode} lock on {}",
+                    wait.owner,
+                    path.display()
+                )?,
+                None => write!(
+                    f,
+                    "{:?} waits for a {mode} lock on an unnamed file",
+                    wait.owner
+                )?,
+            }
+        }
+        write!(f, ", held by {:?}", self.cycle[0].owner)
+    }
+}
+
+impl std::error::Error for DeadlockReport {}
+
+/// Records that `owner` is about to acquire a lock on `file`, failing if a
+/// blocking acquisition would deadlock. Returns `None` if detection is off.
+pub(crate) fn begin<const WRITE: bool, const BLOCK: bool, T: AsOpenFile>(
+    file: &T,
+    owner: Owner,
+) -> io::Result<Option<Acquisition>> {
+    let detection = detection();
+    if detection == Detection::Off {
+        return Ok(None);
+    }
+    let id = file_id(file)?;
+    let path = path_of(file).ok();
+    let mut graph = GRAPH.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut report = None;
+    if BLOCK {
+        let start = Wait {
+            owner,
+            path: path.clone(),
+            write: WRITE,
+        };
+        if let Some(cycle) = graph.cycle(owner, id, WRITE, vec![start]) {
+            if detection == Detection::Error {
+                return Err(io::Error::new(
+                    ErrorKind::Deadlock,
+                    DeadlockReport { cycle },
+                ));
+            }
+            report = Some(DeadlockReport { cycle });
+        }
+    }
+    let serial = graph.next;
+    graph.next += 1;
+    if BLOCK {
+        graph.waits.push(Edge {
+            serial,
+            id,
+            owner,
+            write: WRITE,
+            path: path.clone(),
+        });
+    }
+    drop(graph);
+    if let Some(report) = report {
+        // Called without holding the graph, in case the hook locks files.
+        let hook = HOOK.lock().unwrap_or_else(PoisonError::into_inner).clone();
+        if let Some(hook) = hook {
+            hook(&report);
+        }
+    }
+    Ok(Some(Acquisition {
+        serial,
+        id,
+        owner,
+        write: WRITE,
+        path,
+    }))
+}
+
+impl Acquisition {
+    /// Records that the lock was acquired.
+    pub(crate) fn hold(self) -> Hold {
+        let mut graph = GRAPH.lock().unwrap_or_else(PoisonError::into_inner);
+        graph.waits.retain(|edge| edge.serial != self.serial);
+        graph.holds.push(Edge {
+            serial: self.serial,
+            id: self.id,
+            owner: self.owner,
+            write: self.write,
+            path: self.path.clone(),
+        });
+        Hold {
+            serial: self.serial,
+        }
+    }
+}
+
+/// Forget the wait if the acquisition failed.
+impl Drop for Acquisition {
+    fn drop(&mut self) {
+        let mut graph = GRAPH.lock().unwrap_or_else(PoisonError::into_inner);
+        graph.waits.retain(|edge| edge.serial != self.serial);
+    }
+}
+
+impl Drop for Hold {
+    fn drop(&mut self) {
+        let mut graph = GRAPH.lock().unwrap_or_else(PoisonError::into_inner);
+        graph.holds.retain(|edge| edge.serial != self.serial);
+    }
+}
+
+impl Graph {
+    /// Follows the owners holding conflicting locks on `id`, and the locks
+    /// they wait for, looking for `origin`.
+    fn cycle(&self, origin: Owner, id: FileId, write: bool, path: Vec<Wait>) -> Option<Vec<Wait>> {
+        let holders = self
+            .holds
+            .iter()
+            .filter(|hold| hold.id == id && (write || hold.write));
+        for holder in holders {
+            if holder.owner == origin {
+                return Some(path);
+            }
+            if path.iter().any(|wait| wait.owner == holder.owner) {
+                continue;
+            }
+            // Owners that are not blocked will eventually release their locks.
+            let Some(wait) = self.waits.iter().find(|wait| wait.owner == holder.owner) else {
+                continue;
+            };
+            let mut path = path.clone();
+            path.push(Wait {
+                owner: wait.owner,
+                path: wait.path.clone(),
+                write: wait.write,
+            });
+            if let Some(cycle) = self.cycle(origin, wait.id, wait.write, path) {
+                return Some(cycle);
+            }
+        }
+        None
+    }
+}
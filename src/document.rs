@@ -0,0 +1,222 @@
+//! Serialized documents that are read under a shared lock and updated under
+//! an exclusive lock.
+//!
+//! A [`LockedDocument`] reads and writes a whole file in one of the supported
+//! [`Format`]s. Updates are written to a temporary file that then replaces the
+//! document, so readers never observe a partially written document, even if
+//! the writer crashes.
+//!
+//! # Example
+//!
+//! ```
+//! use std::collections::BTreeMap;
+//! use async_fd_lock::document::{Json, LockedDocument};
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let document = LockedDocument::<BTreeMap<String, u64>, Json>::new(dir.path().join("counts.json"));
+//!
+//! document.update_or_default(|counts| *counts.entry("cats".into()).or_default() += 1)?;
+//! assert_eq!(document.read()?["cats"], 1);
+//! # std::io::Result::Ok(())
+//! ```
+
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::blocking::{LockRead, LockWrite};
+use crate::sys::file_id;
+use crate::{RwLockReadGuard, RwLockWriteGuard};
+
+/// A serialization format for [`LockedDocument`].
+pub trait Format {
+    /// Parses a document from the contents of its file, failing with
+    /// [`ErrorKind::InvalidData`] if they are malformed.
+    fn deserialize<T: DeserializeOwned>(bytes: &[u8]) -> io::Result<T>;
+
+    /// Encodes a document into the contents of its file.
+    fn serialize<T: Serialize>(value: &T) -> io::Result<Vec<u8>>;
+}
+
+/// Pretty-printed JSON.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json;
+
+/// TOML.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Toml;
+
+/// A document of type `T` stored at a path in format `F`.
+pub struct LockedDocument<T, F> {
+    path: PathBuf,
+    marker: PhantomData<fn() -> (T, F)>,
+}
+
+impl Format for Json {
+    fn deserialize<T: DeserializeOwned>(bytes: &[u8]) -> io::Result<T> {
+        serde_json::from_slice(bytes).map_err(|error| io::Error::new(ErrorKind::InvalidData, error))
+    }
+
+    fn serialize<T: Serialize>(value: &T) -> io::Result<Vec<u8>> {
+        let mut bytes = serde_json::to_vec_pretty(value)
+            .map_err(|error| io::Error::new(ErrorKind::InvalidData, error))?;
+        bytes.push(b'\n');
+        Ok(bytes)
+    }
+}
+
+impl Format for Toml {
+    fn deserialize<T: DeserializeOwned>(bytes: &[u8]) -> io::Result<T> {
+        let text = std::str::from_utf8(bytes)
+            .map_err(|error| io::Error::new(ErrorKind::InvalidData, error))?;
+        toml::from_str(text).map_err(|error| io::Error::new(ErrorKind::InvalidData, error))
+    }
+
+    fn serialize<T: Serialize>(value: &T) -> io::Result<Vec<u8>> {
+        toml::to_string(value)
+            .map(String::into_bytes)
+            .map_err(|error| io::Error::new(ErrorKind::InvalidData, error))
+    }
+}
+
+impl<T, F> LockedDocument<T, F>
+where
+    T: Serialize + DeserializeOwned,
+    F: Format,
+{
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            marker: PhantomData,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the document under a shared lock.
+    pub fn read(&self) -> io::Result<T> {
+        let mut guard = self.lock_read()?;
+        let mut bytes = Vec::new();
+        guard.read_to_end(&mut bytes)?;
+        F::deserialize(&bytes)
+    }
+
+    /// Reads the document, lets `update` modify it and writes it back, all
+    /// under an exclusive lock.
+    ///
+    /// The document is only written back if it changed. Fails with
+    /// [`ErrorKind::NotFound`] if the document does not exist.
+    pub fn update<R>(&self, update: impl FnOnce(&mut T) -> R) -> io::Result<R> {
+        let (guard, original) = self.lock_write(false)?;
+        let mut document = F::deserialize(&original)?;
+        let result = update(&mut document);
+        self.write_back(&guard, &original, &document)?;
+        Ok(result)
+    }
+
+    /// Like [`LockedDocument::update`], but starts from `T::default()` if the
+    /// document does not exist or is empty.
+    pub fn update_or_default<R>(&self, update: impl FnOnce(&mut T) -> R) -> io::Result<R>
+    where
+        T: Default,
+    {
+        let (guard, original) = self.lock_write(true)?;
+        let mut document = if original.is_empty() {
+            T::default()
+        } else {
+            F::deserialize(&original)?
+        };
+        let result = update(&mut document);
+        self.write_back(&guard, &original, &document)?;
+        Ok(result)
+    }
+
+    fn lock_read(&self) -> io::Result<RwLockReadGuard<File>> {
+        loop {
+            let guard = File::open(&self.path)?.lock_read()?;
+            if self.is_current(guard.inner())? {
+                return Ok(guard);
+            }
+        }
+    }
+
+    /// Locks the document, returning the guard and the current contents.
+    fn lock_write(&self, create: bool) -> io::Result<(RwLockWriteGuard<File>, Vec<u8>)> {
+        loop {
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(create)
+                .truncate(false)
+                .open(&self.path)?;
+            let mut guard = file.lock_write()?;
+            if self.is_current(guard.inner())? {
+                let mut bytes = Vec::new();
+                guard.read_to_end(&mut bytes)?;
+                return Ok((guard, bytes));
+            }
+        }
+    }
+
+    /// Returns whether `file` is still the document, rather than one that was
+    /// replaced while waiting for its lock.
+    fn is_current(&self, file: &File) -> io::Result<bool> {
+        match File::open(&self.path) {
+            Ok(current) => Ok(file_id(file)? == file_id(&current)?),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Atomically replaces the document locked by `guard` with `document`,
+    /// unless it serializes to the `original` contents.
+    fn write_back(
+        &self,
+        guard: &RwLockWriteGuard<File>,
+        original: &[u8],
+        document: &T,
+    ) -> io::Result<()> {
+        let bytes = F::serialize(document)?;
+        if bytes == original {
+            return Ok(());
+        }
+
+        let mut temp_path = self.path.clone().into_os_string();
+        temp_path.push(format!(".{}.tmp", std::process::id()));
+        let result = (|| {
+            let mut temp = File::create(&temp_path)?;
+            temp.set_permissions(guard.inner().metadata()?.permissions())?;
+            temp.write_all(&bytes)?;
+            temp.sync_all()?;
+            fs::rename(&temp_path, &self.path)
+        })();
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        result
+    }
+}
+
+impl<T, F> fmt::Debug for LockedDocument<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LockedDocument")
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+impl<T, F> Clone for LockedDocument<T, F> {
+    fn clone(&self) -> Self {
+        Self {
+            path: self.path.clone(),
+            marker: PhantomData,
+        }
+    }
+}
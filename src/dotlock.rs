@@ -0,0 +1,348 @@
+//! Exclusive locks based on lock files, for filesystems without working `flock`.
+//!
+//! Some NFS and FUSE mounts fail `flock` with `ENOLCK`, or silently accept it
+//! without locking anything. A [`DotLock`] instead locks `<path>.lock` by
+//! atomically linking a file recording its owner to that path, and unlocks it
+//! by removing it. The owner is recorded so that locks left behind by crashed
+//! processes can be broken. Breaking a lock is itself guarded by
+//! `<path>.lock.break`, so that two processes never break the same lock.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use async_fd_lock::dotlock::DotLock;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let path = dir.path().join("foo.txt");
+//! let dotlock = DotLock::new(&path).stale_after(Duration::from_secs(60));
+//!
+//! let guard = dotlock.lock_write(std::fs::File::create(&path)?)?;
+//! assert!(dotlock.lock_path().exists());
+//! guard.release()?;
+//! assert!(!dotlock.lock_path().exists());
+//! # std::io::Result::Ok(())
+//! ```
+
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::strategy::Held;
+use crate::sys::{hostname, process_exists, AsOpenFile};
+use crate::{LockError, LockWriteResult, RwLockWriteGuard};
+
+/// How long a crashed process may leave `<path>.lock.break` behind before
+/// it is removed. Breaking a lock takes a few filesystem operations.
+const BREAK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An exclusive lock on `<path>.lock`.
+#[derive(Debug, Clone)]
+pub struct DotLock {
+    lock_path: PathBuf,
+    stale_after: Option<Duration>,
+    break_dead_owners: bool,
+    retry_interval: Duration,
+}
+
+/// The owner of a lock file, as recorded when it was created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Owner {
+    /// The process ID of the owner.
+    pub pid: u32,
+    /// The host the owner runs on.
+    pub hostname: String,
+    /// When the lock file was created.
+    pub acquired_at: SystemTime,
+}
+
+/// A lock file created by this process.
+#[derive(Debug)]
+pub(crate) struct DotLockFile {
+    path: PathBuf,
+    contents: String,
+}
+
+impl DotLock {
+    /// Creates a lock for the file at `path`, using `<path>.lock` as the lock
+    /// file.
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        let mut lock_path = OsString::from(path.as_ref());
+        lock_path.push(".lock");
+        Self {
+            lock_path: lock_path.into(),
+            stale_after: None,
+            break_dead_owners: true,
+            retry_interval: Duration::from_millis(100),
+        }
+    }
+
+    /// Breaks lock files that have not been modified for longer than `age`.
+    pub fn stale_after(mut self, age: Duration) -> Self {
+        self.stale_after = Some(age);
+        self
+    }
+
+    /// Whether to break lock files whose owner ran on this host and is no
+    /// longer running. Enabled by default.
+    pub fn break_dead_owners(mut self, enabled: bool) -> Self {
+        self.break_dead_owners = enabled;
+        self
+    }
+
+    /// Sets how long [`DotLock::lock_write`] waits between attempts.
+    pub fn retry_interval(mut self, interval: Duration) -> Self {
+        self.retry_interval = interval;
+        self
+    }
+
+    /// Returns the path of the lock file.
+    pub fn lock_path(&self) -> &Path {
+        &self.lock_path
+    }
+
+    /// Returns the owner of the lock file, if it exists.
+    pub fn owner(&self) -> io::Result<Option<Owner>> {
+        match fs::read_to_string(&self.lock_path) {
+            Ok(contents) => Ok(Owner::parse(&contents)),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Creates the lock file, waiting for it to be removed if it exists.
+    pub fn lock_write<T: AsOpenFile>(&self, file: T) -> LockWriteResult<T> {
+        match self.acquire_blocking::<true>() {
+            Ok(lock_file) => Ok(RwLockWriteGuard::from_held(file, Held::dotlock(lock_file))),
+            Err(error) => Err(LockError::new(file, error)),
+        }
+    }
+
+    /// Creates the lock file, failing with [`ErrorKind::WouldBlock`] if it
+    /// exists.
+    pub fn try_lock_write<T: AsOpenFile>(&self, file: T) -> LockWriteResult<T> {
+        match self.acquire_blocking::<false>() {
+            Ok(lock_file) => Ok(RwLockWriteGuard::from_held(file, Held::dotlock(lock_file))),
+            Err(error) => Err(LockError::new(file, error)),
+        }
+    }
+
+    /// Like [`DotLock::lock_write`], but waits on a blocking task.
+    #[cfg(feature = "async")]
+    pub async fn lock_write_async<T>(&self, file: T) -> LockWriteResult<T>
+    where
+        T: AsOpenFile + Send + 'static,
+    {
+        let dotlock = self.clone();
+        tokio::task::spawn_blocking(move || dotlock.lock_write(file))
+            .await
+            .expect("the blocking task is not cancelable")
+    }
+
+    pub(crate) fn acquire_blocking<const BLOCK: bool>(&self) -> io::Result<DotLockFile> {
+        loop {
+            match self.acquire() {
+                Err(error) if BLOCK && error.kind() == ErrorKind::WouldBlock => {
+                    thread::sleep(self.retry_interval)
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn acquire(&self) -> io::Result<DotLockFile> {
+        // The owner is written to a private file first and then linked into
+        // place, so that the lock file is never observed without its owner.
+        let contents = Owner::current().to_string();
+        let temp_path = self.private_sibling("tmp");
+        let mut temp = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)?;
+        let linked = temp.write_all(contents.as_bytes()).and_then(|()| loop {
+            match fs::hard_link(&temp_path, &self.lock_path) {
+                Err(error) if error.kind() == ErrorKind::AlreadyExists => {
+                    if !self.break_if_stale()? {
+                        return Err(ErrorKind::WouldBlock.into());
+                    }
+                }
+                result => return result,
+            }
+        });
+        drop(temp);
+        let _ = fs::remove_file(&temp_path);
+        linked?;
+        Ok(DotLockFile {
+            path: self.lock_path.clone(),
+            contents,
+        })
+    }
+
+    /// Removes the lock file if it is stale. Returns whether acquisition should
+    /// be retried.
+    fn break_if_stale(&self) -> io::Result<bool> {
+        let break_path = self.sibling("break");
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&break_path)
+        {
+            Ok(_) => {}
+            // Someone else is breaking the lock.
+            Err(error) if error.kind() == ErrorKind::AlreadyExists => {
+                let abandoned = fs::metadata(&break_path)
+                    .and_then(|metadata| metadata.modified())
+                    .is_ok_and(|modified| modified.elapsed().unwrap_or_default() > BREAK_TIMEOUT);
+                if abandoned {
+                    let _ = fs::remove_file(&break_path);
+                }
+                return Ok(abandoned);
+            }
+            Err(error) => return Err(error),
+        }
+        let broken = self.break_locked();
+        let _ = fs::remove_file(&break_path);
+        broken
+    }
+
+    /// Like [`DotLock::break_if_stale`], while holding `<path>.lock.break`.
+    fn break_locked(&self) -> io::Result<bool> {
+        let (contents, modified) = match read_with_mtime(&self.lock_path) {
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(true),
+            result => result?,
+        };
+        // The owner of an empty lock file may still be recording itself, as
+        // lock files created by other tools are not linked into place.
+        if contents.is_empty() {
+            return Ok(false);
+        }
+        let expired = self
+            .stale_after
+            .is_some_and(|age| modified.elapsed().unwrap_or_default() > age);
+        let abandoned = self.break_dead_owners
+            && Owner::parse(&contents).is_some_and(|owner| {
+                owner.hostname == hostname() && process_exists(owner.pid) == Some(false)
+            });
+        if !expired && !abandoned {
+            return Ok(false);
+        }
+
+        // Move the lock file out of the way before removing it, so that a lock
+        // file created in the meantime by someone else is not removed instead.
+        // Only its owner could have released a stale lock file since, and
+        // somebody else acquired it again.
+        let broken_path = self.private_sibling("broken");
+        match fs::rename(&self.lock_path, &broken_path) {
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(true),
+            result => result?,
+        }
+        let broken = fs::read_to_string(&broken_path);
+        if !broken.is_ok_and(|broken| broken == contents) {
+            // Put it back, unless yet another process acquired the lock since.
+            if let Err(error) = fs::hard_link(&broken_path, &self.lock_path) {
+                let _ = fs::remove_file(&broken_path);
+                return Err(error);
+            }
+        }
+        fs::remove_file(&broken_path)?;
+        Ok(true)
+    }
+
+    /// Returns `<path>.lock.<suffix>`.
+    fn sibling(&self, suffix: &str) -> PathBuf {
+        let mut path = self.lock_path.clone().into_os_string();
+        path.push(".");
+        path.push(suffix);
+        path.into()
+    }
+
+    /// Returns a path next to the lock file that is private to this call.
+    fn private_sibling(&self, kind: &str) -> PathBuf {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        // The clock alone may be too coarse to tell threads apart.
+        let serial = NEXT.fetch_add(1, Ordering::Relaxed);
+        self.sibling(&format!(
+            "{kind}.{}.{serial}.{}",
+            std::process::id(),
+            unique()
+        ))
+    }
+}
+
+impl Owner {
+    fn current() -> Self {
+        Self {
+            pid: std::process::id(),
+            hostname: hostname(),
+            acquired_at: SystemTime::now(),
+        }
+    }
+
+    fn parse(contents: &str) -> Option<Self> {
+        let mut pid = None;
+        let mut hostname = None;
+        let mut acquired_at = None;
+        for line in contents.lines() {
+            match line.split_once('=')? {
+                ("pid", value) => pid = value.parse().ok(),
+                ("hostname", value) => hostname = Some(value.to_owned()),
+                ("acquired_at", value) => {
+                    acquired_at = value.parse().ok().map(Duration::from_millis)
+                }
+                _ => {}
+            }
+        }
+        Some(Self {
+            pid: pid?,
+            hostname: hostname?,
+            acquired_at: UNIX_EPOCH + acquired_at?,
+        })
+    }
+}
+
+impl std::fmt::Display for Owner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let acquired_at = self
+            .acquired_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        writeln!(f, "pid={}", self.pid)?;
+        writeln!(f, "hostname={}", self.hostname)?;
+        writeln!(f, "acquired_at={}", acquired_at.as_millis())
+    }
+}
+
+impl DotLockFile {
+    /// Removes the lock file, unless it was broken and replaced by someone
+    /// else.
+    pub(crate) fn remove(&self) -> io::Result<()> {
+        match fs::read_to_string(&self.path) {
+            Ok(contents) if contents == self.contents => fs::remove_file(&self.path),
+            Ok(_) => Err(io::Error::other(
+                "the lock file was broken by another process",
+            )),
+            Err(error) if error.kind() == ErrorKind::NotFound => Err(io::Error::new(
+                ErrorKind::NotFound,
+                "the lock file was broken by another process",
+            )),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+fn read_with_mtime(path: &Path) -> io::Result<(String, SystemTime)> {
+    let contents = fs::read_to_string(path)?;
+    let modified = fs::metadata(path)?.modified()?;
+    Ok((contents, modified))
+}
+
+fn unique() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+}
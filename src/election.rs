@@ -0,0 +1,175 @@
+//! Leader election between processes on one host.
+//!
+//! Every replica creates a [`LeaderElection`] on the same file and
+//! [campaigns](LeaderElection::campaign) for leadership, which polls
+//! `try_lock_write` on the file until it succeeds. The winner holds the
+//! exclusive lock in a [`Leadership`] until it steps down, drops it or exits.
+//! Changes of leadership are published on a [`watch`] channel.
+//!
+//! # Example
+//!
+//! ```
+//! use async_fd_lock::election::LeaderElection;
+//!
+//! # tokio_test::block_on(async {
+//! let dir = tempfile::tempdir().unwrap();
+//! let election = LeaderElection::new(dir.path().join("leader"));
+//! let mut leader = election.watch();
+//!
+//! let leadership = election.campaign().await?;
+//! assert!(*leader.borrow_and_update());
+//!
+//! leadership.step_down()?;
+//! leader.changed().await.unwrap();
+//! assert!(!*leader.borrow());
+//! # std::io::Result::Ok(())
+//! # }).unwrap();
+//! ```
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+use crate::blocking::LockWrite;
+use crate::{LockStrategy, RwLockWriteGuard};
+
+/// A candidate for leadership, identified by the file it locks.
+///
+/// Clones share the same leadership state.
+#[derive(Debug, Clone)]
+pub struct LeaderElection {
+    path: PathBuf,
+    strategy: LockStrategy,
+    retry_interval: Duration,
+    leader: Arc<watch::Sender<bool>>,
+}
+
+/// The leadership won by a [`LeaderElection`], held until it is dropped or
+/// [stepped down](Leadership::step_down).
+#[must_use = "if unused the leadership will immediately be given up"]
+#[derive(Debug)]
+pub struct Leadership {
+    guard: Option<RwLockWriteGuard<File>>,
+    leader: Arc<watch::Sender<bool>>,
+}
+
+impl LeaderElection {
+    /// Creates a candidate that competes for the lock on the file at `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            strategy: LockStrategy::default(),
+            retry_interval: Duration::from_millis(500),
+            leader: Arc::new(watch::channel(false).0),
+        }
+    }
+
+    /// Sets the mechanism used to lock the file.
+    pub fn strategy(mut self, strategy: LockStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Sets how long [`LeaderElection::campaign`] waits between attempts.
+    pub fn retry_interval(mut self, interval: Duration) -> Self {
+        self.retry_interval = interval;
+        self
+    }
+
+    /// Returns the path of the file that the candidates lock.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns whether this candidate currently holds the leadership.
+    pub fn is_leader(&self) -> bool {
+        *self.leader.borrow()
+    }
+
+    /// Returns a receiver that observes this candidate winning and giving up
+    /// the leadership.
+    pub fn watch(&self) -> watch::Receiver<bool> {
+        self.leader.subscribe()
+    }
+
+    /// Attempts to take the lock until it succeeds, returning the
+    /// leadership.
+    pub async fn campaign(&self) -> io::Result<Leadership> {
+        let path = self.path.clone();
+        let mut file = tokio::task::spawn_blocking(move || {
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)
+        })
+        .await
+        .expect("the blocking task is not cancelable")?;
+        loop {
+            // Other strategies than `flock` may take a while even when they
+            // do not wait for the lock.
+            let strategy = self.strategy;
+            let attempt = tokio::task::spawn_blocking(move || file.try_lock_write_with(strategy))
+                .await
+                .expect("the blocking task is not cancelable");
+            match attempt {
+                Ok(guard) => {
+                    self.leader.send_replace(true);
+                    return Ok(Leadership {
+                        guard: Some(guard),
+                        leader: self.leader.clone(),
+                    });
+                }
+                Err(error) if error.error.kind() == ErrorKind::WouldBlock => {
+                    file = error.file;
+                    tokio::time::sleep(self.retry_interval).await;
+                }
+                Err(error) => return Err(error.error),
+            }
+        }
+    }
+}
+
+impl Leadership {
+    /// Returns the guard of the lock that grants the leadership, for example
+    /// to record the leader in the file.
+    pub fn guard(&self) -> &RwLockWriteGuard<File> {
+        self.guard
+            .as_ref()
+            .expect("guard only removed when stepping down")
+    }
+
+    /// Returns a mutable reference to the guard of the lock, for example to
+    /// write to the file.
+    pub fn guard_mut(&mut self) -> &mut RwLockWriteGuard<File> {
+        self.guard
+            .as_mut()
+            .expect("guard only removed when stepping down")
+    }
+
+    /// Gives up the leadership by releasing the lock.
+    pub fn step_down(mut self) -> io::Result<()> {
+        let guard = self
+            .guard
+            .take()
+            .expect("guard only removed when stepping down");
+        // Announced first, so that this candidate never claims to lead while
+        // another one does.
+        self.leader.send_replace(false);
+        guard.release().map(drop)
+    }
+}
+
+impl Drop for Leadership {
+    fn drop(&mut self) {
+        if let Some(guard) = self.guard.take() {
+            self.leader.send_replace(false);
+            drop(guard);
+        }
+    }
+}
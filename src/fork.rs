@@ -0,0 +1,168 @@
+//! Keeping locks from outliving their holder in forked child processes.
+//!
+//! A child process created by `fork` inherits the file descriptors of its
+//! parent, and `flock` and OFD locks belong to the open file behind them, so
+//! the child keeps the locks of its parent alive until it closes them, even
+//! after the parent exited. Releasing them in the child would release them for
+//! the parent as well, so guards dropped in a process other than the one that
+//! acquired them leave the lock alone.
+//!
+//! To keep a child from holding on to locks:
+//!
+//! - If it runs another program, enable [`set_cloexec`], so that the files
+//!   that locks are acquired through are closed on `exec`. Files opened by the
+//!   standard library are already, but not necessarily those adopted from
+//!   elsewhere.
+//! - Otherwise, call [`relinquish`] in the child right after `fork`, which
+//!   detaches it from the locks acquired by its parent.
+//!
+//! # Example
+//!
+//! ```
+//! use std::process::Command;
+//! use async_fd_lock::blocking::LockWrite;
+//! use async_fd_lock::fork;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! fork::set_cloexec(true);
+//!
+//! let guard = std::fs::File::create(dir.path().join("foo.txt"))?.lock_write()?;
+//! // The child does not inherit the locked file.
+//! assert!(Command::new("true").status()?.success());
+//! guard.release()?;
+//! # std::io::Result::Ok(())
+//! ```
+
+use std::io::{self, ErrorKind};
+use std::mem::ManuallyDrop;
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError, TryLockError};
+
+use rustix::fs::{Mode, OFlags};
+use rustix::io::FdFlags;
+
+use crate::sys::AsOpenFile;
+
+static CLOEXEC: AtomicBool = AtomicBool::new(false);
+
+/// The descriptors through which locks are held, by the process that
+/// acquired them.
+static TRACKED: Mutex<Vec<Entry>> = Mutex::new(Vec::new());
+
+struct Entry {
+    serial: u64,
+    pid: u32,
+    fd: RawFd,
+}
+
+/// The process that acquired a lock, and the descriptor it is held through.
+#[derive(Debug)]
+pub(crate) struct Tracked {
+    serial: u64,
+    pid: u32,
+}
+
+/// Sets whether the files that locks are acquired through are marked
+/// close-on-exec.
+pub fn set_cloexec(enabled: bool) {
+    CLOEXEC.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns whether the files that locks are acquired through are marked
+/// close-on-exec.
+pub fn cloexec() -> bool {
+    CLOEXEC.load(Ordering::Relaxed)
+}
+
+/// Detaches this process from the locks acquired by its parent before it
+/// forked, returning how many were detached.
+///
+/// The descriptors through which the parent held the locks are replaced with
+/// `/dev/null`, so that they no longer keep the locks alive, while the guards
+/// and files owning them stay valid. Does nothing outside of a forked child.
+///
+/// Only the descriptor a guard holds the lock through is known. A guard split
+/// with `into_parts` or `map` holds the lock through a duplicate, and the
+/// inner value keeps the original open, which still keeps the lock alive
+/// until the child closes it. Lock files and brokered locks are not held
+/// through a descriptor, and are left alone.
+///
+/// Fails with [`ErrorKind::WouldBlock`] if another thread of the parent was
+/// acquiring or releasing a lock when it forked.
+pub fn relinquish() -> io::Result<usize> {
+    let mut tracked = match TRACKED.try_lock() {
+        Ok(tracked) => tracked,
+        Err(TryLockError::Poisoned(error)) => error.into_inner(),
+        Err(TryLockError::WouldBlock) => return Err(ErrorKind::WouldBlock.into()),
+    };
+    let pid = process::id();
+    if tracked.iter().all(|entry| entry.pid == pid) {
+        return Ok(0);
+    }
+    let null = rustix::fs::open("/dev/null", OFlags::RDWR | OFlags::CLOEXEC, Mode::empty())?;
+    let mut count = 0;
+    for entry in tracked.iter().filter(|entry| entry.pid != pid) {
+        // SAFETY: the descriptor stays owned by its guard, which removes the
+        // entry before it is closed.
+        let mut fd = ManuallyDrop::new(unsafe { OwnedFd::from_raw_fd(entry.fd) });
+        rustix::io::dup2(&null, &mut fd)?;
+        count += 1;
+    }
+    tracked.retain(|entry| entry.pid == pid);
+    Ok(count)
+}
+
+/// Marks `file` close-on-exec if enabled.
+pub(crate) fn prepare<T: AsOpenFile>(file: &T) -> io::Result<()> {
+    if cloexec() {
+        let flags = rustix::io::fcntl_getfd(file)?;
+        rustix::io::fcntl_setfd(file, flags | FdFlags::CLOEXEC)?;
+    }
+    Ok(())
+}
+
+impl Tracked {
+    /// Tracks a lock acquired by the current process.
+    pub(crate) fn new() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self {
+            serial: NEXT.fetch_add(1, Ordering::Relaxed),
+            pid: process::id(),
+        }
+    }
+
+    /// Returns whether the lock was acquired by another process, which this
+    /// one was forked from.
+    pub(crate) fn is_inherited(&self) -> bool {
+        self.pid != process::id()
+    }
+
+    /// Records that the lock is held through `file`, instead of any
+    /// descriptor recorded before.
+    pub(crate) fn track<T: AsOpenFile>(&self, file: &T) {
+        use rustix::fd::AsRawFd;
+
+        if self.is_inherited() {
+            return;
+        }
+        let fd = file.as_fd().as_raw_fd();
+        let mut tracked = TRACKED.lock().unwrap_or_else(PoisonError::into_inner);
+        match tracked.iter_mut().find(|entry| entry.serial == self.serial) {
+            Some(entry) => entry.fd = fd,
+            None => tracked.push(Entry {
+                serial: self.serial,
+                pid: self.pid,
+                fd,
+            }),
+        }
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        let mut tracked = TRACKED.lock().unwrap_or_else(PoisonError::into_inner);
+        tracked.retain(|entry| entry.serial != self.serial);
+    }
+}
@@ -0,0 +1,200 @@
+//! Enforcement of an order in which lock files are acquired.
+//!
+//! Lock files can be assigned numeric levels with [`set_level`]. An [`Owner`]
+//! holding a lock on a file at some level must not acquire a lock on a file
+//! at a lower level, so that owners taking several locks always take them in
+//! the same order and cannot deadlock each other. Acquiring locks out of order
+//! panics, or fails with [`ErrorKind::Deadlock`] carrying a [`LevelViolation`],
+//! depending on the [`Violation`] mode.
+//!
+//! The checks only run in debug builds, or with the `hierarchy` feature.
+//! Files without a level are never checked, and a lock keeps the level its
+//! file had when it was acquired. Locks acquired asynchronously outside of a
+//! [`scope`](crate::reentrant::scope) are not checked either, since they have
+//! no owner of their own.
+//!
+//! # Example
+//!
+//! ```
+//! use async_fd_lock::blocking::LockWrite;
+//! use async_fd_lock::hierarchy;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let accounts = dir.path().join("accounts");
+//! let journal = dir.path().join("journal");
+//! std::fs::File::create(&accounts)?;
+//! std::fs::File::create(&journal)?;
+//! hierarchy::set_level(&accounts, 1)?;
+//! hierarchy::set_level(&journal, 2)?;
+//!
+//! let _journal = std::fs::File::create(&journal)?.lock_write()?;
+//! # #[cfg(any(debug_assertions, feature = "hierarchy"))]
+//! assert!(std::fs::File::create(&accounts)?.lock_write().is_err());
+//! # std::io::Result::Ok(())
+//! ```
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+use crate::reentrant::Owner;
+use crate::sys::{file_id, AsOpenFile, FileId};
+
+/// The levels assigned to files.
+static LEVELS: Mutex<Vec<Level>> = Mutex::new(Vec::new());
+
+/// The locks on files with a level held by each owner.
+static HELD: Mutex<Vec<Rank>> = Mutex::new(Vec::new());
+
+static ERROR: AtomicBool = AtomicBool::new(true);
+
+/// What to do about locks acquired out of order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Violation {
+    /// Panic with a [`LevelViolation`] message.
+    Panic,
+    /// Fail with [`ErrorKind::Deadlock`]. This is the default, with or
+    /// without the `hierarchy` feature.
+    Error,
+}
+
+/// A lock acquired at a lower level than a lock held by the same owner.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevelViolation {
+    /// The file being locked.
+    pub acquiring: PathBuf,
+    /// The level of the file being locked.
+    pub acquiring_level: u32,
+    /// The file already locked by the owner.
+    pub holding: PathBuf,
+    /// The level of the file already locked by the owner.
+    pub holding_level: u32,
+}
+
+/// A lock on a file with a level, removed from the held locks on drop.
+#[derive(Debug)]
+pub(crate) struct Ranked {
+    serial: u64,
+}
+
+struct Level {
+    id: FileId,
+    path: PathBuf,
+    level: u32,
+}
+
+struct Rank {
+    serial: u64,
+    owner: Owner,
+    level: u32,
+    path: PathBuf,
+}
+
+/// Assigns `level` to the file at `path`, which must exist.
+///
+/// Levels belong to the file rather than the path, so a file replaced at the
+/// same path needs to be assigned a level again.
+pub fn set_level(path: impl AsRef<Path>, level: u32) -> io::Result<()> {
+    let path = path.as_ref();
+    let id = file_id(&File::open(path)?)?;
+    let mut levels = LEVELS.lock().unwrap_or_else(PoisonError::into_inner);
+    levels.retain(|entry| entry.id != id);
+    levels.push(Level {
+        id,
+        path: path.to_owned(),
+        level,
+    });
+    Ok(())
+}
+
+/// Removes the level of the file at `path`, if it has one.
+pub fn clear_level(path: impl AsRef<Path>) -> io::Result<()> {
+    let id = file_id(&File::open(path)?)?;
+    let mut levels = LEVELS.lock().unwrap_or_else(PoisonError::into_inner);
+    levels.retain(|entry| entry.id != id);
+    Ok(())
+}
+
+/// Sets what to do about locks acquired out of order.
+pub fn set_violation(violation: Violation) {
+    ERROR.store(violation == Violation::Error, Ordering::Relaxed);
+}
+
+/// Returns what is done about locks acquired out of order.
+pub fn violation() -> Violation {
+    match ERROR.load(Ordering::Relaxed) {
+        false => Violation::Panic,
+        true => Violation::Error,
+    }
+}
+
+impl fmt::Display for LevelViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "acquiring {} at level {} while holding {} at level {}",
+            self.acquiring.display(),
+            self.acquiring_level,
+            self.holding.display(),
+            self.holding_level,
+        )
+    }
+}
+
+impl std::error::Error for LevelViolation {}
+
+/// Checks that `owner` may lock `file`, recording the lock until the returned
+/// record is dropped. Returns `None` if the file has no level.
+pub(crate) fn enter<T: AsOpenFile>(file: &T, owner: Owner) -> io::Result<Option<Ranked>> {
+    if !cfg!(any(debug_assertions, feature = "hierarchy")) {
+        return Ok(None);
+    }
+    let (level, path) = {
+        let levels = LEVELS.lock().unwrap_or_else(PoisonError::into_inner);
+        if levels.is_empty() {
+            return Ok(None);
+        }
+        let id = file_id(file)?;
+        match levels.iter().find(|entry| entry.id == id) {
+            Some(entry) => (entry.level, entry.path.clone()),
+            None => return Ok(None),
+        }
+    };
+    let mut held = HELD.lock().unwrap_or_else(PoisonError::into_inner);
+    let higher = held
+        .iter()
+        .filter(|rank| rank.owner == owner && rank.level > level)
+        .max_by_key(|rank| rank.level);
+    if let Some(higher) = higher {
+        let violation = LevelViolation {
+            acquiring: path,
+            acquiring_level: level,
+            holding: higher.path.clone(),
+            holding_level: higher.level,
+        };
+        drop(held);
+        return match self::violation() {
+            Violation::Panic => panic!("{violation}"),
+            Violation::Error => Err(io::Error::new(ErrorKind::Deadlock, violation)),
+        };
+    }
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let serial = NEXT.fetch_add(1, Ordering::Relaxed);
+    held.push(Rank {
+        serial,
+        owner,
+        level,
+        path,
+    });
+    Ok(Some(Ranked { serial }))
+}
+
+impl Drop for Ranked {
+    fn drop(&mut self) {
+        let mut held = HELD.lock().unwrap_or_else(PoisonError::into_inner);
+        held.retain(|rank| rank.serial != self.serial);
+    }
+}
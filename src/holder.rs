@@ -0,0 +1,241 @@
+//! Sidecar files that tell contenders who holds a lock and why.
+//!
+//! The holder of an exclusive lock can publish a [`Holder`] record with
+//! [`RwLockWriteGuard::publish_holder`](crate::RwLockWriteGuard::publish_holder).
+//! It is written to `<path>.holder` next to the locked file, or to any other
+//! path with `publish_holder_at`, and removed right before the lock is
+//! released. A contender that fails to take the lock with
+//! [`ErrorKind::WouldBlock`] can read it through [`LockError::holder`].
+//!
+//! The record is a versioned text file:
+//!
+//! ```text
+//! async-fd-lock holder v1
+//! pid=<pid>
+//! hostname=<hostname>
+//! program=<program name>
+//! purpose=<purpose, with backslashes and newlines escaped>
+//! acquired_at=<unix millis>
+//! ```
+//!
+//! Readers ignore unknown keys, so later minor revisions can add fields.
+//!
+//! # Example
+//!
+//! ```
+//! use async_fd_lock::blocking::LockWrite;
+//! use async_fd_lock::holder;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let path = dir.path().join("job");
+//! let sidecar = holder::sidecar_path(&path);
+//!
+//! let mut guard = std::fs::File::create(&path)?.lock_write()?;
+//! guard.publish_holder_at(&sidecar, "nightly backup")?;
+//!
+//! let error = std::fs::File::open(&path)?.try_lock_write().unwrap_err();
+//! let holder = error.holder_at(&sidecar)?.expect("published above");
+//! assert_eq!(holder.purpose, "nightly backup");
+//!
+//! guard.release()?;
+//! assert!(!sidecar.exists());
+//! # std::io::Result::Ok(())
+//! ```
+
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::sys::{hostname, path_of, AsOpenFile};
+use crate::LockError;
+
+const MAGIC: &str = "async-fd-lock holder v1";
+
+/// Who holds a lock and why, as published by the holder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Holder {
+    /// The process ID of the holder.
+    pub pid: u32,
+    /// The host the holder runs on.
+    pub hostname: String,
+    /// The name of the holder's executable.
+    pub program: String,
+    /// Why the holder took the lock.
+    pub purpose: String,
+    /// When the holder published the record.
+    pub acquired_at: SystemTime,
+}
+
+/// A sidecar file written by this process.
+#[derive(Debug)]
+pub(crate) struct Sidecar {
+    path: PathBuf,
+    contents: String,
+}
+
+impl Holder {
+    /// Describes the current process.
+    pub fn current(purpose: impl Into<String>) -> Self {
+        let program = std::env::args_os()
+            .next()
+            .map(PathBuf::from)
+            .and_then(|program| Some(program.file_name()?.to_string_lossy().into_owned()))
+            .unwrap_or_default();
+        Self {
+            pid: std::process::id(),
+            hostname: hostname(),
+            program,
+            purpose: purpose.into(),
+            acquired_at: SystemTime::now(),
+        }
+    }
+
+    /// Reads the record in the sidecar file at `sidecar`, returning `None` if
+    /// it does not exist or is not a record of a known version.
+    pub fn read(sidecar: impl AsRef<Path>) -> io::Result<Option<Self>> {
+        match fs::read(sidecar) {
+            Ok(contents) => Ok(Self::parse(&String::from_utf8_lossy(&contents))),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn parse(contents: &str) -> Option<Self> {
+        let mut lines = contents.lines();
+        if lines.next()? != MAGIC {
+            return None;
+        }
+        let mut pid = None;
+        let mut hostname = None;
+        let mut program = None;
+        let mut purpose = None;
+        let mut acquired_at = None;
+        for line in lines {
+            match line.split_once('=')? {
+                ("pid", value) => pid = value.parse().ok(),
+                ("hostname", value) => hostname = Some(value.to_owned()),
+                ("program", value) => program = Some(unescape(value)),
+                ("purpose", value) => purpose = Some(unescape(value)),
+                ("acquired_at", value) => {
+                    acquired_at = value.parse().ok().map(Duration::from_millis)
+                }
+                _ => {}
+            }
+        }
+        Some(Self {
+            pid: pid?,
+            hostname: hostname?,
+            program: program?,
+            purpose: purpose?,
+            acquired_at: UNIX_EPOCH + acquired_at?,
+        })
+    }
+}
+
+impl std::fmt::Display for Holder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let acquired_at = self
+            .acquired_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        writeln!(f, "{MAGIC}")?;
+        writeln!(f, "pid={}", self.pid)?;
+        writeln!(f, "hostname={}", self.hostname)?;
+        writeln!(f, "program={}", escape(&self.program))?;
+        writeln!(f, "purpose={}", escape(&self.purpose))?;
+        writeln!(f, "acquired_at={}", acquired_at.as_millis())
+    }
+}
+
+/// Returns the default sidecar path for the file at `path`.
+pub fn sidecar_path(path: impl AsRef<Path>) -> PathBuf {
+    let mut sidecar = OsString::from(path.as_ref());
+    sidecar.push(".holder");
+    sidecar.into()
+}
+
+impl<T: AsOpenFile> LockError<T> {
+    /// Reads the record published by the holder of the lock at the default
+    /// sidecar path of the file.
+    ///
+    /// Returns `None` unless the lock was held by someone else. Only supported
+    /// on Linux, where the path of an open file can be recovered.
+    pub fn holder(&self) -> io::Result<Option<Holder>> {
+        if self.error.kind() != ErrorKind::WouldBlock {
+            return Ok(None);
+        }
+        Holder::read(sidecar_path(path_of(&self.file)?))
+    }
+
+    /// Like [`LockError::holder`], but reads the sidecar file at `sidecar`.
+    pub fn holder_at(&self, sidecar: impl AsRef<Path>) -> io::Result<Option<Holder>> {
+        if self.error.kind() != ErrorKind::WouldBlock {
+            return Ok(None);
+        }
+        Holder::read(sidecar)
+    }
+}
+
+impl Sidecar {
+    /// Atomically writes `holder` to `path`.
+    pub(crate) fn write(path: PathBuf, holder: &Holder) -> io::Result<Self> {
+        let contents = holder.to_string();
+        let mut temp_path = path.clone().into_os_string();
+        temp_path.push(format!(".{}.tmp", std::process::id()));
+        let result = (|| {
+            let mut temp = fs::File::create(&temp_path)?;
+            temp.write_all(contents.as_bytes())?;
+            fs::rename(&temp_path, &path)
+        })();
+        if let Err(error) = result {
+            let _ = fs::remove_file(&temp_path);
+            return Err(error);
+        }
+        Ok(Self { path, contents })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Removes the sidecar file, unless somebody else replaced it.
+    pub(crate) fn remove(&self) -> io::Result<()> {
+        match fs::read_to_string(&self.path) {
+            Ok(contents) if contents == self.contents => fs::remove_file(&self.path),
+            Ok(_) => Ok(()),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+pub(crate) fn default_sidecar<T: AsOpenFile>(file: &T) -> io::Result<PathBuf> {
+    Ok(sidecar_path(path_of(file)?))
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
@@ -0,0 +1,209 @@
+//! Handing a held lock over to a child process.
+//!
+//! [`RwLockWriteGuard::into_inheritable`] turns a guard into an
+//! [`InheritableLock`], a descriptor that refers to the same open file as the
+//! guard, and so to the same lock. Passing it to a command with
+//! [`InheritableLock::pass_to`] keeps it open across `exec` in the children
+//! of that command only, and tells them which descriptor to adopt with
+//! [`RwLockWriteGuard::from_inherited`], without locking the file again. A
+//! `tokio::process::Command` is passed through its `as_std_mut` method.
+//!
+//! Only `flock` and open file description locks belong to the open file and
+//! can be handed over.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use std::process::Command;
+//! use async_fd_lock::blocking::LockWrite;
+//! use async_fd_lock::RwLockWriteGuard;
+//!
+//! // In the parent:
+//! let guard = File::create("foo.txt")?.lock_write()?;
+//! let (file, lock) = guard.into_inheritable()?;
+//! let mut child = lock.pass_to(&mut Command::new("child")).spawn()?;
+//! // The child holds the lock from now on.
+//! drop(file);
+//! child.wait()?;
+//!
+//! // In the child:
+//! let guard = RwLockWriteGuard::from_inherited()?;
+//! guard.release()?;
+//! # std::io::Result::Ok(())
+//! ```
+
+use std::env;
+use std::fs::File;
+use std::io::{self, ErrorKind};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use rustix::fs::FileType;
+use rustix::io::FdFlags;
+
+use crate::strategy::Held;
+use crate::sys::AsOpenFile;
+use crate::{LockError, LockStrategy, RwLockWriteGuard};
+
+/// The environment variable naming the descriptor of an inherited lock, and
+/// the strategy it was acquired with.
+pub const INHERITED_ENV: &str = "ASYNC_FD_LOCK_INHERITED";
+
+/// A descriptor for a held lock, to pass to child processes.
+///
+/// The descriptor is closed on `exec`, except in the children it is passed to.
+/// Dropping it closes the descriptor in this process without releasing the
+/// lock.
+#[derive(Debug)]
+pub struct InheritableLock {
+    fd: OwnedFd,
+    strategy: LockStrategy,
+}
+
+impl InheritableLock {
+    /// Returns the mechanism used to hold the lock.
+    pub fn strategy(&self) -> LockStrategy {
+        self.strategy
+    }
+
+    /// Returns the environment variable that names the descriptor to a
+    /// child.
+    ///
+    /// Prefer [`InheritableLock::pass_to`], which also keeps the descriptor
+    /// open across `exec` in the child, and works with
+    /// `tokio::process::Command` through its `as_std_mut` method.
+    pub fn env(&self) -> (&'static str, String) {
+        let strategy = match self.strategy {
+            LockStrategy::Fcntl => "fcntl",
+            _ => "flock",
+        };
+        (INHERITED_ENV, format!("{}:{strategy}", self.fd.as_raw_fd()))
+    }
+
+    /// Passes the lock to the children spawned by `command`.
+    ///
+    /// The command keeps the descriptor open in this process until it is
+    /// dropped.
+    pub fn pass_to(self, command: &mut Command) -> &mut Command {
+        let (key, value) = self.env();
+        let fd = self.fd;
+        // SAFETY: `fcntl` is async-signal-safe.
+        unsafe {
+            command.pre_exec(move || {
+                let flags = rustix::io::fcntl_getfd(&fd)?;
+                rustix::io::fcntl_setfd(&fd, flags - FdFlags::CLOEXEC)?;
+                Ok(())
+            });
+        }
+        command.env(key, value)
+    }
+}
+
+impl AsFd for InheritableLock {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl AsRawFd for InheritableLock {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl<T: AsOpenFile> RwLockWriteGuard<T> {
+    /// Turns the guard into a descriptor for the lock that child processes
+    /// inherit, returning the inner file along with it.
+    ///
+    /// The lock is held until every descriptor of the open file is closed, so
+    /// the inner file keeps it alive as long as it is open in this process.
+    /// Fails with [`ErrorKind::Unsupported`] if the lock does not belong to
+    /// the open file, returning the guard back.
+    pub fn into_inheritable(self) -> Result<(T, InheritableLock), LockError<Self>> {
+        let strategy = self.strategy();
+        if !matches!(strategy, LockStrategy::Flock | LockStrategy::Fcntl) {
+            let error = io::Error::new(
+                ErrorKind::Unsupported,
+                "only locks owned by the open file can be inherited",
+            );
+            return Err(LockError::new(self, error));
+        }
+        // Only kept open on `exec` in the children it is passed to.
+        let fd = match rustix::io::fcntl_dupfd_cloexec(self.inner(), 0) {
+            Ok(fd) => fd,
+            Err(error) => return Err(LockError::new(self, error.into())),
+        };
+        let (file, lock) = self.into_parts()?;
+        lock.into_held().hand_over();
+        Ok((file, InheritableLock { fd, strategy }))
+    }
+
+    /// Wraps `file`, on which an exclusive lock is already held with
+    /// `strategy`, for example by a parent process, without locking it again.
+    ///
+    /// The lock is released like any other when the guard is dropped. Fails
+    /// with [`ErrorKind::Unsupported`] unless `strategy` is
+    /// [`LockStrategy::Flock`] or [`LockStrategy::Fcntl`], the locks that
+    /// belong to the open file, returning the file back.
+    pub fn adopt(file: T, strategy: LockStrategy) -> Result<Self, LockError<T>> {
+        if !matches!(strategy, LockStrategy::Flock | LockStrategy::Fcntl) {
+            let error = io::Error::new(
+                ErrorKind::Unsupported,
+                "only locks owned by the open file can be adopted",
+            );
+            return Err(LockError::new(file, error));
+        }
+        Ok(Self::from_held(file, Held::os(strategy)))
+    }
+}
+
+impl RwLockWriteGuard<File> {
+    /// Adopts the lock passed to this process with
+    /// [`InheritableLock::pass_to`], without locking the file again.
+    ///
+    /// The variable naming the descriptor stays in the environment, which
+    /// children of this process inherit, so remove it from commands with
+    /// `env_remove(INHERITED_ENV)` if they might adopt locks themselves.
+    ///
+    /// Fails with [`ErrorKind::NotFound`] if no lock was passed, or if it was
+    /// adopted already, and with [`ErrorKind::InvalidData`] if the variable
+    /// does not name an open regular file other than standard input, output
+    /// or error.
+    pub fn from_inherited() -> io::Result<Self> {
+        static ADOPTED: AtomicBool = AtomicBool::new(false);
+
+        let not_found = || io::Error::new(ErrorKind::NotFound, "no lock was inherited");
+        let value = env::var(INHERITED_ENV).map_err(|_| not_found())?;
+        let invalid = || io::Error::new(ErrorKind::InvalidData, "invalid inherited lock");
+        let (fd, strategy) = value.split_once(':').ok_or_else(invalid)?;
+        let fd: RawFd = fd.parse().map_err(|_| invalid())?;
+        let strategy = match strategy {
+            "flock" => LockStrategy::Flock,
+            "fcntl" => LockStrategy::Fcntl,
+            _ => return Err(invalid()),
+        };
+        if fd <= 2 {
+            return Err(invalid());
+        }
+        // SAFETY: until it is validated, the descriptor is only passed to
+        // `fcntl` and `fstat`, which fail harmlessly if it is not open.
+        let borrowed = unsafe { BorrowedFd::borrow_raw(fd) };
+        let flags = rustix::io::fcntl_getfd(borrowed).map_err(|_| invalid())?;
+        let stat = rustix::fs::fstat(borrowed).map_err(|_| invalid())?;
+        if FileType::from_raw_mode(stat.st_mode) != FileType::RegularFile {
+            return Err(invalid());
+        }
+        if ADOPTED.swap(true, Ordering::Relaxed) {
+            return Err(not_found());
+        }
+        // Not passed on to this process's own children.
+        rustix::io::fcntl_setfd(borrowed, flags | FdFlags::CLOEXEC)?;
+        // SAFETY: the parent left the descriptor open for this process to
+        // adopt, it refers to a regular file, and it is only adopted once.
+        let file = File::from(unsafe { OwnedFd::from_raw_fd(fd) });
+        Self::adopt(file, strategy).map_err(io::Error::from)
+    }
+}
@@ -0,0 +1,309 @@
+//! Exclusive locks that advertise the liveness of their holder.
+//!
+//! A [`Lease`] holds an exclusive lock on a dedicated lock file and
+//! periodically overwrites the beginning of that file (after the first byte)
+//! with a heartbeat record, from a task on the current tokio runtime if
+//! there is one and the `async` feature is enabled, or from a background
+//! thread otherwise. Any other party can
+//! [`probe`] the lock file to learn whether the lock is free, or whether its
+//! holder is still alive.
+//!
+//! The heartbeat is a single line of text:
+//!
+//! ```text
+//! async-fd-lock lease v1 pid=<pid> at=<unix millis> every=<interval millis>
+//! ```
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use async_fd_lock::blocking::LockWrite;
+//! use async_fd_lock::lease::{self, Lease, LeaseStatus};
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let path = dir.path().join("job.lock");
+//!
+//! let guard = std::fs::File::create(&path)?.lock_write()?;
+//! let lease = Lease::new(guard, Duration::from_secs(1))?;
+//! assert!(matches!(lease::probe(&path)?, LeaseStatus::Fresh(_)));
+//!
+//! lease.release()?;
+//! assert!(matches!(lease::probe(&path)?, LeaseStatus::Free));
+//! # std::io::Result::Ok(())
+//! ```
+
+use std::fs::File;
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::blocking::LockRead;
+use crate::sys::{duplicate, AsOpenFile};
+use crate::RwLockWriteGuard;
+
+const MAGIC: &str = "async-fd-lock lease v1";
+
+/// Where the heartbeat record starts. On Windows, other handles cannot read
+/// the locked first byte of the file.
+const RECORD_OFFSET: u64 = 1;
+
+/// The length every heartbeat record is padded to, so that a newer record
+/// always completely overwrites an older one.
+const RECORD_LEN: usize = 96;
+
+/// A heartbeat is considered stale once it is older than this many intervals.
+const STALE_INTERVALS: u32 = 3;
+
+/// An exclusive lock whose holder periodically publishes a heartbeat.
+///
+/// The heartbeat stops when the lease is dropped or released.
+#[must_use = "if unused the RwLock will immediately unlock"]
+#[derive(Debug)]
+pub struct Lease<T: AsOpenFile> {
+    // Declared first, so that the heartbeat stops before the lock is released.
+    heartbeat: Heartbeater,
+    guard: RwLockWriteGuard<T>,
+}
+
+/// A heartbeat published by the holder of a [`Lease`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Heartbeat {
+    /// The process ID of the holder.
+    pub pid: u32,
+    /// When the heartbeat was written.
+    pub at: SystemTime,
+    /// How often the holder publishes a heartbeat.
+    pub interval: Duration,
+}
+
+/// The state of a lock file, as reported by [`probe`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeaseStatus {
+    /// Nobody holds an exclusive lock on the file.
+    Free,
+    /// The lock is held and its holder published a heartbeat recently.
+    Fresh(Heartbeat),
+    /// The lock is held, but its holder has not published a heartbeat for
+    /// several intervals.
+    Stale(Heartbeat),
+    /// The lock is held by someone who does not publish heartbeats.
+    Unknown,
+}
+
+#[derive(Debug)]
+enum Heartbeater {
+    Thread {
+        stop: Arc<(Mutex<bool>, Condvar)>,
+        thread: Option<JoinHandle<()>>,
+    },
+    /// The file is taken out when the heartbeat stops, which waits for a
+    /// heartbeat being written to finish.
+    #[cfg(feature = "async")]
+    Task {
+        file: Arc<Mutex<Option<File>>>,
+        task: tokio::task::JoinHandle<()>,
+    },
+}
+
+impl<T: AsOpenFile> Lease<T> {
+    /// Starts publishing a heartbeat every `interval` into the file locked by
+    /// `guard`.
+    ///
+    /// The first heartbeat is written before this function returns. The
+    /// contents of the file are overwritten.
+    pub fn new(guard: RwLockWriteGuard<T>, interval: Duration) -> io::Result<Self> {
+        let file = duplicate(guard.inner())?;
+        write_heartbeat(&file, interval)?;
+        #[cfg(feature = "async")]
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let file = Arc::new(Mutex::new(Some(file)));
+            let task = runtime.spawn(beat_async(file.clone(), interval));
+            return Ok(Self {
+                heartbeat: Heartbeater::Task { file, task },
+                guard,
+            });
+        }
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread = thread::Builder::new()
+            .name("async-fd-lock-lease".into())
+            .spawn({
+                let stop = stop.clone();
+                move || beat(file, interval, &stop)
+            })?;
+        Ok(Self {
+            heartbeat: Heartbeater::Thread {
+                stop,
+                thread: Some(thread),
+            },
+            guard,
+        })
+    }
+
+    /// Returns a reference to the guard of the lock.
+    pub fn guard(&self) -> &RwLockWriteGuard<T> {
+        &self.guard
+    }
+
+    /// Returns a mutable reference to the guard of the lock, for example to
+    /// access the file. Writes to the beginning of the file may be
+    /// overwritten by the next heartbeat.
+    pub fn guard_mut(&mut self) -> &mut RwLockWriteGuard<T> {
+        &mut self.guard
+    }
+
+    /// Stops the heartbeat while keeping the lock held.
+    pub fn into_guard(self) -> RwLockWriteGuard<T> {
+        let Self { heartbeat, guard } = self;
+        drop(heartbeat);
+        guard
+    }
+
+    /// Stops the heartbeat and releases the lock, returning the inner file.
+    pub fn release(self) -> io::Result<T> {
+        self.into_guard().release()
+    }
+}
+
+impl Heartbeat {
+    /// Returns how long ago the heartbeat was written.
+    pub fn age(&self) -> Duration {
+        self.at.elapsed().unwrap_or_default()
+    }
+
+    /// Returns whether the heartbeat is older than several intervals.
+    pub fn is_stale(&self) -> bool {
+        self.age() > self.interval * STALE_INTERVALS
+    }
+
+    fn parse(record: &str) -> Option<Self> {
+        let fields = record.lines().next()?.strip_prefix(MAGIC)?;
+        let mut pid = None;
+        let mut at = None;
+        let mut interval = None;
+        for field in fields.split_whitespace() {
+            match field.split_once('=')? {
+                ("pid", value) => pid = value.parse().ok(),
+                ("at", value) => at = value.parse().ok().map(Duration::from_millis),
+                ("every", value) => interval = value.parse().ok().map(Duration::from_millis),
+                _ => {}
+            }
+        }
+        Some(Self {
+            pid: pid?,
+            at: UNIX_EPOCH + at?,
+            interval: interval?,
+        })
+    }
+}
+
+impl Drop for Heartbeater {
+    fn drop(&mut self) {
+        match self {
+            Self::Thread { stop, thread } => {
+                let (stopped, changed) = &**stop;
+                *stopped.lock().unwrap_or_else(PoisonError::into_inner) = true;
+                changed.notify_all();
+                if let Some(thread) = thread.take() {
+                    let _ = thread.join();
+                }
+            }
+            #[cfg(feature = "async")]
+            Self::Task { file, task } => {
+                task.abort();
+                file.lock().unwrap_or_else(PoisonError::into_inner).take();
+            }
+        }
+    }
+}
+
+/// Reports whether the lock file at `path` is locked, and if so, whether its
+/// holder is alive.
+pub fn probe(path: impl AsRef<Path>) -> io::Result<LeaseStatus> {
+    let file = match File::open(path)?.try_lock_read() {
+        Ok(guard) => {
+            guard.release()?;
+            return Ok(LeaseStatus::Free);
+        }
+        Err(error) if error.error.kind() == ErrorKind::WouldBlock => error.file,
+        Err(error) => return Err(error.error),
+    };
+    let mut record = Vec::new();
+    (&file).seek(SeekFrom::Start(RECORD_OFFSET))?;
+    (&file).take(RECORD_LEN as u64).read_to_end(&mut record)?;
+    Ok(match Heartbeat::parse(&String::from_utf8_lossy(&record)) {
+        Some(heartbeat) if heartbeat.is_stale() => LeaseStatus::Stale(heartbeat),
+        Some(heartbeat) => LeaseStatus::Fresh(heartbeat),
+        None => LeaseStatus::Unknown,
+    })
+}
+
+fn beat(file: File, interval: Duration, stop: &(Mutex<bool>, Condvar)) {
+    let (stopped, changed) = stop;
+    let mut stopped = stopped.lock().unwrap_or_else(PoisonError::into_inner);
+    loop {
+        stopped = changed
+            .wait_timeout_while(stopped, interval, |stopped| !*stopped)
+            .unwrap_or_else(PoisonError::into_inner)
+            .0;
+        if *stopped {
+            return;
+        }
+        // A failed heartbeat is not fatal: if the failures persist, the lease
+        // turns stale, which is exactly what probes should observe.
+        let _ = write_heartbeat(&file, interval);
+    }
+}
+
+#[cfg(feature = "async")]
+async fn beat_async(file: Arc<Mutex<Option<File>>>, interval: Duration) {
+    loop {
+        tokio::time::sleep(interval).await;
+        let file = file.clone();
+        let beating = tokio::task::spawn_blocking(move || {
+            let file = file.lock().unwrap_or_else(PoisonError::into_inner);
+            // See `beat` for why a failed heartbeat is ignored.
+            file.as_ref()
+                .map(|file| write_heartbeat(file, interval))
+                .is_some()
+        });
+        if !matches!(beating.await, Ok(true)) {
+            return;
+        }
+    }
+}
+
+fn write_heartbeat(file: &File, interval: Duration) -> io::Result<()> {
+    let at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let mut record = format!(
+        "{MAGIC} pid={} at={} every={}",
+        std::process::id(),
+        at.as_millis(),
+        interval.as_millis()
+    );
+    record.truncate(RECORD_LEN - 1);
+    let record = format!("{record:<width$}\n", width = RECORD_LEN - 1);
+    write_all_at(file, record.as_bytes(), RECORD_OFFSET)
+}
+
+fn write_all_at(file: &File, buf: &[u8], offset: u64) -> io::Result<()> {
+    cfg_if::cfg_if! {
+        if #[cfg(unix)] {
+            std::os::unix::fs::FileExt::write_all_at(file, buf, offset)
+        } else if #[cfg(windows)] {
+            let mut written = 0;
+            while written < buf.len() {
+                written += std::os::windows::fs::FileExt::seek_write(
+                    file,
+                    &buf[written..],
+                    offset + written as u64,
+                )?;
+            }
+            Ok(())
+        }
+    }
+}
@@ -40,23 +40,64 @@
 #![deny(missing_debug_implementations, nonstandard_style)]
 #![cfg_attr(doc, warn(missing_docs))]
 
-use sys::AsOpenFileExt;
-
+mod buffered;
+mod checksum;
+mod mapped_read_guard;
+mod mapped_write_guard;
+#[cfg(feature = "mmap")]
+mod mmap;
+mod owned_lock;
 mod read_guard;
+mod strategy;
 mod write_guard;
 
+pub mod append_log;
+#[cfg(unix)]
+pub mod broker;
+pub mod counter;
+pub mod deadlock;
+#[cfg(feature = "serde")]
+pub mod document;
+pub mod dotlock;
+#[cfg(feature = "async")]
+pub mod election;
+#[cfg(unix)]
+pub mod fork;
+pub mod hierarchy;
+pub mod holder;
+#[cfg(unix)]
+pub mod inherit;
+pub mod lease;
+pub mod named;
+pub mod reentrant;
+pub mod semaphore;
+pub mod transaction;
+#[cfg(feature = "async")]
+pub mod watcher;
+
 pub(crate) mod error;
 pub(crate) mod sys;
+#[cfg(feature = "testing")]
+pub mod testing;
 
 pub use error::*;
+pub use mapped_read_guard::MappedRwLockReadGuard;
+pub use mapped_write_guard::MappedRwLockWriteGuard;
+#[cfg(feature = "mmap")]
+pub use mmap::{LockedMmap, LockedMmapMut};
 #[cfg(feature = "async")]
 pub use nonblocking::*;
+pub use owned_lock::OwnedLock;
 pub use read_guard::RwLockReadGuard;
+pub use strategy::LockStrategy;
 pub use sys::AsOpenFile;
 pub use write_guard::RwLockWriteGuard;
 
 pub mod blocking {
     use super::*;
+    use strategy::acquire;
+
+    pub use crate::buffered::blocking::{LockedBufReader, LockedBufWriter};
 
     pub trait LockRead: AsOpenFile + std::io::Read {
         fn lock_read(self) -> LockReadResult<Self>
@@ -66,6 +107,14 @@ pub mod blocking {
         fn try_lock_read(self) -> LockReadResult<Self>
         where
             Self: Sized;
+
+        fn lock_read_with(self, strategy: LockStrategy) -> LockReadResult<Self>
+        where
+            Self: Sized;
+
+        fn try_lock_read_with(self, strategy: LockStrategy) -> LockReadResult<Self>
+        where
+            Self: Sized;
     }
 
     pub trait LockWrite: AsOpenFile + std::io::Write {
@@ -76,6 +125,14 @@ pub mod blocking {
         fn try_lock_write(self) -> LockWriteResult<Self>
         where
             Self: Sized;
+
+        fn lock_write_with(self, strategy: LockStrategy) -> LockWriteResult<Self>
+        where
+            Self: Sized;
+
+        fn try_lock_write_with(self, strategy: LockStrategy) -> LockWriteResult<Self>
+        where
+            Self: Sized;
     }
 
     impl<T> LockRead for T
@@ -83,14 +140,22 @@ pub mod blocking {
         T: AsOpenFile + std::io::Read,
     {
         fn lock_read(self) -> LockReadResult<Self> {
-            match self.acquire_lock_blocking::<false, true>() {
+            self.lock_read_with(LockStrategy::default())
+        }
+
+        fn try_lock_read(self) -> LockReadResult<Self> {
+            self.try_lock_read_with(LockStrategy::default())
+        }
+
+        fn lock_read_with(self, strategy: LockStrategy) -> LockReadResult<Self> {
+            match acquire::<false, true, _>(&self, strategy) {
                 Ok(guard) => Ok(RwLockReadGuard::new(self, guard)),
                 Err(error) => Err(LockError::new(self, error)),
             }
         }
 
-        fn try_lock_read(self) -> LockReadResult<Self> {
-            match self.acquire_lock_blocking::<false, false>() {
+        fn try_lock_read_with(self, strategy: LockStrategy) -> LockReadResult<Self> {
+            match acquire::<false, false, _>(&self, strategy) {
                 Ok(guard) => Ok(RwLockReadGuard::new(self, guard)),
                 Err(error) => Err(LockError::new(self, error)),
             }
@@ -102,14 +167,22 @@ pub mod blocking {
         T: AsOpenFile + std::io::Write,
     {
         fn lock_write(self) -> LockWriteResult<Self> {
-            match self.acquire_lock_blocking::<true, true>() {
+            self.lock_write_with(LockStrategy::default())
+        }
+
+        fn try_lock_write(self) -> LockWriteResult<Self> {
+            self.try_lock_write_with(LockStrategy::default())
+        }
+
+        fn lock_write_with(self, strategy: LockStrategy) -> LockWriteResult<Self> {
+            match acquire::<true, true, _>(&self, strategy) {
                 Ok(guard) => Ok(RwLockWriteGuard::new(self, guard)),
                 Err(error) => Err(LockError::new(self, error)),
             }
         }
 
-        fn try_lock_write(self) -> LockWriteResult<Self> {
-            match self.acquire_lock_blocking::<true, false>() {
+        fn try_lock_write_with(self, strategy: LockStrategy) -> LockWriteResult<Self> {
+            match acquire::<true, false, _>(&self, strategy) {
                 Ok(guard) => Ok(RwLockWriteGuard::new(self, guard)),
                 Err(error) => Err(LockError::new(self, error)),
             }
@@ -124,22 +197,60 @@ pub mod nonblocking {
     use std::io;
     use sys::{AsOpenFileExt, RwLockGuard};
 
+    pub use crate::buffered::nonblocking::{LockedBufReader, LockedBufWriter};
+
     async fn lock<const WRITE: bool, const BLOCK: bool, T>(
         file: &T,
+        strategy: LockStrategy,
     ) -> Result<RwLockGuard<<T as AsOpenFileExt>::OwnedOpenFile>, io::Error>
     where
         T: AsOpenFile + Sync + 'static,
     {
         let handle = file.borrow_open_file().try_clone_to_owned()?;
+        // Futures outside of a scope have no owner of their own.
+        let owner = reentrant::Owner::scoped();
         let (sync_send, async_recv) = tokio::sync::oneshot::channel();
-        tokio::task::spawn_blocking(move || {
-            let guard = handle.acquire_lock_blocking::<WRITE, BLOCK>();
+        let task = tokio::task::spawn_blocking(move || {
+            let guard = strategy::acquire_as::<WRITE, BLOCK, _>(&handle, strategy, owner);
             let result = sync_send.send(guard);
             drop(result); // If the guard cannot be sent to the async task, release the lock immediately.
         });
-        async_recv
-            .await
-            .expect("the blocking task is not cancelable")
+        match async_recv.await {
+            Ok(guard) => guard,
+            // The guard is only dropped unsent if acquiring it panicked, for
+            // example because of a level violation.
+            Err(_) => match task.await {
+                Err(error) if error.is_panic() => std::panic::resume_unwind(error.into_panic()),
+                _ => unreachable!("the blocking task is not cancelable"),
+            },
+        }
+    }
+
+    /// Waits for the lock with a [`LockWatcher`](crate::watcher::LockWatcher)
+    /// instead of on a blocking task, falling back to the latter if the file
+    /// cannot be watched.
+    async fn lock_watched<const WRITE: bool, T>(
+        file: &T,
+        strategy: LockStrategy,
+    ) -> Result<RwLockGuard<<T as AsOpenFileExt>::OwnedOpenFile>, io::Error>
+    where
+        T: AsOpenFile + Sync + 'static,
+    {
+        let mut watcher = None;
+        loop {
+            match lock::<WRITE, false, _>(file, strategy).await {
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => {}
+                result => return result,
+            }
+            let watcher = match &mut watcher {
+                Some(watcher) => watcher,
+                None => match crate::watcher::LockWatcher::for_file(file) {
+                    Ok(new) => watcher.insert(new.strategy(strategy)),
+                    Err(_) => return lock::<WRITE, true, _>(file, strategy).await,
+                },
+            };
+            watcher.available(WRITE).await?;
+        }
     }
 
     #[async_trait]
@@ -151,6 +262,25 @@ pub mod nonblocking {
         async fn try_lock_read(self) -> LockReadResult<Self>
         where
             Self: Sized;
+
+        async fn lock_read_with(self, strategy: LockStrategy) -> LockReadResult<Self>
+        where
+            Self: Sized;
+
+        async fn try_lock_read_with(self, strategy: LockStrategy) -> LockReadResult<Self>
+        where
+            Self: Sized;
+
+        /// Like [`LockRead::lock_read`], but waits with a
+        /// [`LockWatcher`](crate::watcher::LockWatcher) instead of occupying a
+        /// blocking thread while the lock is held by someone else.
+        async fn lock_read_watched(self) -> LockReadResult<Self>
+        where
+            Self: Sized;
+
+        async fn lock_read_watched_with(self, strategy: LockStrategy) -> LockReadResult<Self>
+        where
+            Self: Sized;
     }
 
     #[async_trait]
@@ -162,6 +292,25 @@ pub mod nonblocking {
         async fn try_lock_write(self) -> LockWriteResult<Self>
         where
             Self: Sized;
+
+        async fn lock_write_with(self, strategy: LockStrategy) -> LockWriteResult<Self>
+        where
+            Self: Sized;
+
+        async fn try_lock_write_with(self, strategy: LockStrategy) -> LockWriteResult<Self>
+        where
+            Self: Sized;
+
+        /// Like [`LockWrite::lock_write`], but waits with a
+        /// [`LockWatcher`](crate::watcher::LockWatcher) instead of occupying a
+        /// blocking thread while the lock is held by someone else.
+        async fn lock_write_watched(self) -> LockWriteResult<Self>
+        where
+            Self: Sized;
+
+        async fn lock_write_watched_with(self, strategy: LockStrategy) -> LockWriteResult<Self>
+        where
+            Self: Sized;
     }
 
     #[async_trait]
@@ -170,14 +319,33 @@ pub mod nonblocking {
         T: AsOpenFile + tokio::io::AsyncRead + Send + Sync + 'static,
     {
         async fn lock_read(self) -> LockReadResult<Self> {
-            match lock::<false, true, _>(&self).await {
+            self.lock_read_with(LockStrategy::default()).await
+        }
+
+        async fn try_lock_read(self) -> LockReadResult<Self> {
+            self.try_lock_read_with(LockStrategy::default()).await
+        }
+
+        async fn lock_read_with(self, strategy: LockStrategy) -> LockReadResult<Self> {
+            match lock::<false, true, _>(&self, strategy).await {
                 Ok(guard) => Ok(RwLockReadGuard::new(self, guard)),
                 Err(error) => Err(LockError::new(self, error)),
             }
         }
 
-        async fn try_lock_read(self) -> LockReadResult<Self> {
-            match lock::<false, false, _>(&self).await {
+        async fn try_lock_read_with(self, strategy: LockStrategy) -> LockReadResult<Self> {
+            match lock::<false, false, _>(&self, strategy).await {
+                Ok(guard) => Ok(RwLockReadGuard::new(self, guard)),
+                Err(error) => Err(LockError::new(self, error)),
+            }
+        }
+
+        async fn lock_read_watched(self) -> LockReadResult<Self> {
+            self.lock_read_watched_with(LockStrategy::default()).await
+        }
+
+        async fn lock_read_watched_with(self, strategy: LockStrategy) -> LockReadResult<Self> {
+            match lock_watched::<false, _>(&self, strategy).await {
                 Ok(guard) => Ok(RwLockReadGuard::new(self, guard)),
                 Err(error) => Err(LockError::new(self, error)),
             }
@@ -190,17 +358,36 @@ pub mod nonblocking {
         T: AsOpenFile + tokio::io::AsyncWrite + Send + Sync + 'static,
     {
         async fn lock_write(self) -> LockWriteResult<Self> {
-            match lock::<true, true, _>(&self).await {
+            self.lock_write_with(LockStrategy::default()).await
+        }
+
+        async fn try_lock_write(self) -> LockWriteResult<Self> {
+            self.try_lock_write_with(LockStrategy::default()).await
+        }
+
+        async fn lock_write_with(self, strategy: LockStrategy) -> LockWriteResult<Self> {
+            match lock::<true, true, _>(&self, strategy).await {
                 Ok(guard) => Ok(RwLockWriteGuard::new(self, guard)),
                 Err(error) => Err(LockError::new(self, error)),
             }
         }
 
-        async fn try_lock_write(self) -> LockWriteResult<Self> {
-            match lock::<true, false, _>(&self).await {
+        async fn try_lock_write_with(self, strategy: LockStrategy) -> LockWriteResult<Self> {
+            match lock::<true, false, _>(&self, strategy).await {
                 Ok(guard) => Ok(RwLockWriteGuard::new(self, guard)),
                 Err(error) => return Err(LockError::new(self, error)),
             }
         }
+
+        async fn lock_write_watched(self) -> LockWriteResult<Self> {
+            self.lock_write_watched_with(LockStrategy::default()).await
+        }
+
+        async fn lock_write_watched_with(self, strategy: LockStrategy) -> LockWriteResult<Self> {
+            match lock_watched::<true, _>(&self, strategy).await {
+                Ok(guard) => Ok(RwLockWriteGuard::new(self, guard)),
+                Err(error) => Err(LockError::new(self, error)),
+            }
+        }
     }
 }
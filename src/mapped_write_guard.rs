@@ -0,0 +1,246 @@
+use std::{
+    io::{self, BufRead, Read, Seek, Write},
+    pin::Pin,
+};
+
+use cfg_if::cfg_if;
+use pin_project::pin_project;
+
+use crate::{LockStrategy, OwnedLock};
+
+/// An exclusive lock on a file whose handle was wrapped by
+/// [`RwLockWriteGuard::map`](crate::RwLockWriteGuard::map).
+///
+/// The wrapper is dropped before the lock is released, so buffered writers
+/// flush while the lock is still held.
+#[must_use = "if unused the RwLock will immediately unlock"]
+#[derive(Debug)]
+#[pin_project]
+pub struct MappedRwLockWriteGuard<T> {
+    // Declared first, so that it is dropped before the lock is released.
+    #[pin]
+    inner: T,
+    lock: OwnedLock,
+}
+
+impl<T> MappedRwLockWriteGuard<T> {
+    /// Returns the mechanism used to hold the lock.
+    pub fn strategy(&self) -> LockStrategy {
+        self.lock.strategy()
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn inner_pin_mut(self: Pin<&mut Self>) -> Pin<&mut T> {
+        self.project().inner
+    }
+
+    /// Wraps the inner value again while keeping the lock held.
+    pub fn map<U>(self, map: impl FnOnce(T) -> U) -> MappedRwLockWriteGuard<U> {
+        let (inner, lock) = self.into_parts();
+        MappedRwLockWriteGuard::from_parts(map(inner), lock)
+    }
+
+    /// Like [`MappedRwLockWriteGuard::map`], but with a fallible wrapper. The
+    /// lock is released if `map` fails.
+    pub fn try_map<U, E>(
+        self,
+        map: impl FnOnce(T) -> Result<U, E>,
+    ) -> Result<MappedRwLockWriteGuard<U>, E> {
+        let (inner, lock) = self.into_parts();
+        Ok(MappedRwLockWriteGuard::from_parts(map(inner)?, lock))
+    }
+
+    /// Splits the guard into the inner value and the lock, without releasing
+    /// the lock.
+    pub fn into_parts(self) -> (T, OwnedLock) {
+        (self.inner, self.lock)
+    }
+
+    pub fn from_parts(inner: T, lock: OwnedLock) -> Self {
+        Self { inner, lock }
+    }
+
+    /// Flushes the inner value, then releases the lock, returning the inner
+    /// value.
+    ///
+    /// Anything the inner value writes afterwards, for example when it is
+    /// dropped, is no longer covered by the lock. Values that do not implement
+    /// [`Write`] are released by dropping the guard, which drops them first.
+    pub fn release(mut self) -> io::Result<T>
+    where
+        T: Write,
+    {
+        self.inner.flush()?;
+        self.lock.release()?;
+        Ok(self.inner)
+    }
+
+    /// Like [`MappedRwLockWriteGuard::release`], for asynchronous writers.
+    #[cfg(feature = "async")]
+    pub async fn release_async(mut self) -> io::Result<T>
+    where
+        T: tokio::io::AsyncWrite + Unpin,
+    {
+        tokio::io::AsyncWriteExt::flush(&mut self.inner).await?;
+        self.lock.release()?;
+        Ok(self.inner)
+    }
+}
+
+/// Delegate [`Read`] to the inner value.
+impl<T: Read> Read for MappedRwLockWriteGuard<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut<'_>]) -> io::Result<usize> {
+        self.inner.read_vectored(bufs)
+    }
+
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        self.inner.read_to_end(buf)
+    }
+
+    fn read_to_string(&mut self, buf: &mut String) -> io::Result<usize> {
+        self.inner.read_to_string(buf)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.inner.read_exact(buf)
+    }
+}
+
+impl<T: BufRead> BufRead for MappedRwLockWriteGuard<T> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt)
+    }
+
+    fn read_until(&mut self, byte: u8, buf: &mut Vec<u8>) -> io::Result<usize> {
+        self.inner.read_until(byte, buf)
+    }
+
+    fn read_line(&mut self, buf: &mut String) -> io::Result<usize> {
+        self.inner.read_line(buf)
+    }
+}
+
+/// Delegate [`Write`] to the inner value.
+impl<T: Write> Write for MappedRwLockWriteGuard<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        self.inner.write_vectored(bufs)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.inner.write_all(buf)
+    }
+
+    fn write_fmt(&mut self, fmt: std::fmt::Arguments<'_>) -> io::Result<()> {
+        self.inner.write_fmt(fmt)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<T: Seek> Seek for MappedRwLockWriteGuard<T> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+
+    fn rewind(&mut self) -> io::Result<()> {
+        self.inner.rewind()
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        self.inner.stream_position()
+    }
+
+    fn seek_relative(&mut self, offset: i64) -> io::Result<()> {
+        self.inner.seek_relative(offset)
+    }
+}
+
+cfg_if! {
+    if #[cfg(feature = "async")] {
+        use std::task::{Context, Poll};
+        use tokio::io::{AsyncRead, AsyncBufRead, AsyncSeek, AsyncWrite, ReadBuf};
+
+        /// Delegate [`AsyncRead`] to the inner value.
+        impl<T: AsyncRead> AsyncRead for MappedRwLockWriteGuard<T> {
+            fn poll_read(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &mut ReadBuf<'_>,
+            ) -> Poll<io::Result<()>> {
+                self.inner_pin_mut().poll_read(cx, buf)
+            }
+        }
+
+        impl<T: AsyncBufRead> AsyncBufRead for MappedRwLockWriteGuard<T> {
+            fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+                self.inner_pin_mut().poll_fill_buf(cx)
+            }
+
+            fn consume(self: Pin<&mut Self>, amt: usize) {
+                self.inner_pin_mut().consume(amt)
+            }
+        }
+
+        /// Delegate [`AsyncWrite`] to the inner value.
+        impl<T: AsyncWrite> AsyncWrite for MappedRwLockWriteGuard<T> {
+            fn poll_write(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<io::Result<usize>> {
+                self.inner_pin_mut().poll_write(cx, buf)
+            }
+
+            fn poll_write_vectored(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                bufs: &[io::IoSlice<'_>],
+            ) -> Poll<io::Result<usize>> {
+                self.inner_pin_mut().poll_write_vectored(cx, bufs)
+            }
+
+            fn is_write_vectored(&self) -> bool {
+                self.inner.is_write_vectored()
+            }
+
+            fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                self.inner_pin_mut().poll_flush(cx)
+            }
+
+            fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                self.inner_pin_mut().poll_shutdown(cx)
+            }
+        }
+
+        impl<T: AsyncSeek> AsyncSeek for MappedRwLockWriteGuard<T> {
+            fn start_seek(self: Pin<&mut Self>, position: io::SeekFrom) -> io::Result<()> {
+                self.inner_pin_mut().start_seek(position)
+            }
+
+            fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+                self.inner_pin_mut().poll_complete(cx)
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::io;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+use memmap2::{Mmap, MmapMut};
+
+use crate::sys::{AsOpenFile, AsOpenFileExt};
+use crate::{RwLockReadGuard, RwLockWriteGuard};
+
+/// A read-only memory map of a file, borrowed from the guard holding its lock.
+#[derive(Debug)]
+pub struct LockedMmap<'a> {
+    mmap: Mmap,
+    guard: PhantomData<&'a ()>,
+}
+
+/// A writable memory map of a file, borrowed from the guard holding its lock.
+///
+/// Changes are flushed to the file when the map is dropped, which always
+/// happens before the guard can release the lock. Errors while flushing on
+/// drop are ignored, so call [`LockedMmapMut::flush`] when they matter.
+#[derive(Debug)]
+pub struct LockedMmapMut<'a> {
+    mmap: MmapMut,
+    guard: PhantomData<&'a mut ()>,
+}
+
+impl<T: AsOpenFile> RwLockReadGuard<T> {
+    /// Maps the whole file into memory.
+    ///
+    /// # Safety
+    ///
+    /// The lock only keeps out writers that take it as well. The behavior is
+    /// undefined if the file is modified or truncated by anyone ignoring the
+    /// lock while it is mapped. See [`Mmap::map`].
+    pub unsafe fn map_readonly(&self) -> io::Result<LockedMmap<'_>> {
+        Ok(LockedMmap {
+            mmap: Mmap::map(&self.inner().borrow_open_file())?,
+            guard: PhantomData,
+        })
+    }
+}
+
+impl<T: AsOpenFile> RwLockWriteGuard<T> {
+    /// Maps the whole file into memory for reading and writing. The file must
+    /// have been opened for both.
+    ///
+    /// # Safety
+    ///
+    /// The lock only keeps out readers and writers that take it as well. The
+    /// behavior is undefined if the file is accessed by anyone ignoring the
+    /// lock while it is mapped. See [`MmapMut::map_mut`].
+    pub unsafe fn map_mut(&mut self) -> io::Result<LockedMmapMut<'_>> {
+        Ok(LockedMmapMut {
+            mmap: MmapMut::map_mut(&self.inner().borrow_open_file())?,
+            guard: PhantomData,
+        })
+    }
+}
+
+impl LockedMmapMut<'_> {
+    /// Writes outstanding changes to the file, waiting for them to complete.
+    pub fn flush(&self) -> io::Result<()> {
+        self.mmap.flush()
+    }
+}
+
+impl Deref for LockedMmap<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.mmap
+    }
+}
+
+impl AsRef<[u8]> for LockedMmap<'_> {
+    fn as_ref(&self) -> &[u8] {
+        &self.mmap
+    }
+}
+
+impl Deref for LockedMmapMut<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.mmap
+    }
+}
+
+impl DerefMut for LockedMmapMut<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.mmap
+    }
+}
+
+impl AsRef<[u8]> for LockedMmapMut<'_> {
+    fn as_ref(&self) -> &[u8] {
+        &self.mmap
+    }
+}
+
+impl AsMut<[u8]> for LockedMmapMut<'_> {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.mmap
+    }
+}
+
+/// Flush the changes before the guard gets a chance to release the lock.
+impl Drop for LockedMmapMut<'_> {
+    fn drop(&mut self) {
+        let _ = self.mmap.flush();
+    }
+}
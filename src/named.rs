@@ -0,0 +1,199 @@
+//! Locks on logical resources rather than on data files.
+//!
+//! A [`NamedLock`] maps a name such as `"migrations"` to a lock file in a
+//! namespace directory, and locks that file. Names can contain any
+//! character: bytes other than ASCII letters, digits, `-`, `_` and `.` are
+//! percent-encoded, and names whose encoding does not fit in a file name are
+//! truncated and suffixed with a hash of the whole name. Names that only
+//! differ in case share a lock file on case-insensitive filesystems.
+//!
+//! # Example
+//!
+//! ```
+//! use std::io::ErrorKind;
+//! use async_fd_lock::named::NamedLock;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let lock = NamedLock::new(dir.path(), "db/migrations");
+//! assert_eq!(lock.path(), dir.path().join("db%2Fmigrations.lock"));
+//!
+//! let guard = lock.lock_write()?;
+//! let err = lock.try_lock_read().unwrap_err();
+//! assert_eq!(err.kind(), ErrorKind::WouldBlock);
+//! guard.release()?;
+//! # std::io::Result::Ok(())
+//! ```
+
+use std::fmt::Write;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::blocking::{LockRead, LockWrite};
+use crate::{LockStrategy, RwLockReadGuard, RwLockWriteGuard};
+
+/// The longest file name generated for a name, leaving room for temporary
+/// and sidecar files next to it within the usual limit of 255 bytes.
+const MAX_FILE_NAME_LEN: usize = 200;
+
+const EXTENSION: &str = ".lock";
+
+const RESERVED_ON_WINDOWS: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// A read-write lock on a name within a namespace directory.
+#[derive(Debug, Clone)]
+pub struct NamedLock {
+    name: String,
+    path: PathBuf,
+    strategy: LockStrategy,
+}
+
+impl NamedLock {
+    /// Creates a lock on `name`, backed by a lock file in `namespace_dir`.
+    ///
+    /// The directory is created when the lock is first taken.
+    pub fn new(namespace_dir: impl AsRef<Path>, name: impl Into<String>) -> Self {
+        let name = name.into();
+        let path = namespace_dir.as_ref().join(file_name(&name));
+        Self {
+            name,
+            path,
+            strategy: LockStrategy::default(),
+        }
+    }
+
+    /// Sets the mechanism used to lock the lock file.
+    pub fn strategy(mut self, strategy: LockStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the path of the lock file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Takes a shared lock, waiting for exclusive holders to release it.
+    pub fn lock_read(&self) -> io::Result<RwLockReadGuard<File>> {
+        Ok(self.open()?.lock_read_with(self.strategy)?)
+    }
+
+    /// Takes a shared lock, failing with [`ErrorKind::WouldBlock`](io::ErrorKind::WouldBlock)
+    /// if it is held exclusively.
+    pub fn try_lock_read(&self) -> io::Result<RwLockReadGuard<File>> {
+        Ok(self.open()?.try_lock_read_with(self.strategy)?)
+    }
+
+    /// Takes an exclusive lock, waiting for other holders to release it.
+    pub fn lock_write(&self) -> io::Result<RwLockWriteGuard<File>> {
+        Ok(self.open()?.lock_write_with(self.strategy)?)
+    }
+
+    /// Takes an exclusive lock, failing with [`ErrorKind::WouldBlock`](io::ErrorKind::WouldBlock)
+    /// if it is held.
+    pub fn try_lock_write(&self) -> io::Result<RwLockWriteGuard<File>> {
+        Ok(self.open()?.try_lock_write_with(self.strategy)?)
+    }
+
+    /// Like [`NamedLock::lock_read`], but waits on a blocking task.
+    #[cfg(feature = "async")]
+    pub async fn lock_read_async(&self) -> io::Result<RwLockReadGuard<File>> {
+        let lock = self.clone();
+        tokio::task::spawn_blocking(move || lock.lock_read())
+            .await
+            .expect("the blocking task is not cancelable")
+    }
+
+    /// Like [`NamedLock::try_lock_read`], but opens the lock file on a
+    /// blocking task.
+    #[cfg(feature = "async")]
+    pub async fn try_lock_read_async(&self) -> io::Result<RwLockReadGuard<File>> {
+        let lock = self.clone();
+        tokio::task::spawn_blocking(move || lock.try_lock_read())
+            .await
+            .expect("the blocking task is not cancelable")
+    }
+
+    /// Like [`NamedLock::lock_write`], but waits on a blocking task.
+    #[cfg(feature = "async")]
+    pub async fn lock_write_async(&self) -> io::Result<RwLockWriteGuard<File>> {
+        let lock = self.clone();
+        tokio::task::spawn_blocking(move || lock.lock_write())
+            .await
+            .expect("the blocking task is not cancelable")
+    }
+
+    /// Like [`NamedLock::try_lock_write`], but opens the lock file on a
+    /// blocking task.
+    #[cfg(feature = "async")]
+    pub async fn try_lock_write_async(&self) -> io::Result<RwLockWriteGuard<File>> {
+        let lock = self.clone();
+        tokio::task::spawn_blocking(move || lock.try_lock_write())
+            .await
+            .expect("the blocking task is not cancelable")
+    }
+
+    fn open(&self) -> io::Result<File> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.path)
+    }
+}
+
+/// Maps `name` to the name of its lock file.
+fn file_name(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len() + EXTENSION.len());
+    for (index, byte) in name.bytes().enumerate() {
+        match byte {
+            // A leading dot would hide the file, and `.` and `..` are taken.
+            b'.' if index == 0 => escaped.push_str("%2E"),
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' => {
+                escaped.push(byte as char)
+            }
+            _ => write!(escaped, "%{byte:02X}").expect("writing to a string cannot fail"),
+        }
+    }
+    if escaped.is_empty() {
+        // Otherwise `.lock`, a hidden file. No other name encodes to `%`.
+        escaped.push('%');
+    }
+    let stem = escaped.split('.').next().unwrap_or_default();
+    if RESERVED_ON_WINDOWS
+        .iter()
+        .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+    {
+        // Windows opens a device for these, whatever the extension.
+        let first = escaped.remove(0);
+        escaped.insert_str(0, &format!("%{:02X}", first as u8));
+    }
+    // Hashed file names are exactly as long as the limit, and others are
+    // shorter, so the two never collide.
+    if escaped.len() + EXTENSION.len() >= MAX_FILE_NAME_LEN {
+        // Escaped names are ASCII, so any length is a character boundary.
+        let hash = format!("-{:016x}", fnv1a(name.as_bytes()));
+        escaped.truncate(MAX_FILE_NAME_LEN - EXTENSION.len() - hash.len());
+        escaped.push_str(&hash);
+    }
+    escaped.push_str(EXTENSION);
+    escaped
+}
+
+/// The 64-bit FNV-1a hash, which is stable across platforms and releases.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
@@ -0,0 +1,48 @@
+use std::fmt;
+use std::io;
+
+use crate::strategy::Held;
+use crate::sys::{OwnedOpenFile, RwLockGuard};
+use crate::LockStrategy;
+
+/// A lock detached from the file it was acquired through.
+///
+/// Obtained by splitting a guard with `into_parts`. The lock is kept alive
+/// through a duplicate of the file's handle, and released when this value is
+/// dropped. The file keeps the lock alive as well, which matters to forked
+/// children on Unix, see `fork::relinquish`.
+#[must_use = "if unused the RwLock will immediately unlock"]
+pub struct OwnedLock {
+    guard: RwLockGuard<OwnedOpenFile>,
+}
+
+impl OwnedLock {
+    pub(crate) fn new(handle: OwnedOpenFile, held: Held) -> Self {
+        Self {
+            guard: RwLockGuard::new(handle, held),
+        }
+    }
+
+    /// Gives up the duplicate handle without releasing the lock.
+    pub(crate) fn into_held(self) -> Held {
+        self.guard.defuse()
+    }
+
+    /// Returns the mechanism used to hold the lock.
+    pub fn strategy(&self) -> LockStrategy {
+        self.guard.held().strategy()
+    }
+
+    /// Releases the lock.
+    pub fn release(self) -> io::Result<()> {
+        self.guard.release()
+    }
+}
+
+impl fmt::Debug for OwnedLock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OwnedLock")
+            .field("strategy", &self.strategy())
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,383 @@
+//! Locks that the same owner can take again while holding them.
+//!
+//! Locking a file that the caller already holds deadlocks with `fcntl` record
+//! locks and `LockFileEx`, and with `flock` when the file is opened twice. In
+//! re-entrant mode, [`Reentrant`] remembers which locks each [`Owner`] holds
+//! in this process, keyed by the file rather than the handle. Locking a file
+//! again from the same owner returns a nested guard without touching the OS
+//! lock, which is only released once every guard of that owner has been
+//! dropped.
+//!
+//! The owner is the current [`scope`] in async code, where tasks move between
+//! threads and share them, or else the current Tokio task, or else the
+//! current thread. See [`Owner::current`] for futures that share a task.
+//!
+//! # Example
+//!
+//! ```
+//! use std::fs::File;
+//! use async_fd_lock::reentrant::Reentrant;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let path = dir.path().join("foo.txt");
+//! let reentrant = Reentrant::new();
+//!
+//! let outer = reentrant.lock_write(File::create(&path)?)?;
+//! // Would block forever without re-entrant mode.
+//! let inner = reentrant.lock_write(File::options().write(true).open(&path)?)?;
+//! drop(inner);
+//! outer.release()?;
+//! # std::io::Result::Ok(())
+//! ```
+
+use std::fs::File;
+use std::io::{self, ErrorKind, Read, Seek, Write};
+use std::sync::{Mutex, PoisonError};
+use std::thread::{self, ThreadId};
+
+use crate::strategy::acquire_as;
+use crate::sys::{duplicate, file_id, AsOpenFile, FileId};
+use crate::{LockError, LockStrategy, RwLockReadGuard, RwLockWriteGuard};
+
+/// The locks held in re-entrant mode by each owner.
+static HELD: Mutex<Vec<Entry>> = Mutex::new(Vec::new());
+
+/// Takes locks in re-entrant mode.
+///
+/// Locks nest within the [`Owner::current`] at the time they are taken, so in
+/// async code, futures that share a task also share their locks unless they
+/// run in separate [`scope`]s.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Reentrant {
+    strategy: LockStrategy,
+}
+
+/// Who holds a re-entrant lock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Owner {
+    /// A thread, outside of any Tokio task or [`scope`].
+    Thread(ThreadId),
+    /// An async [`scope`].
+    Scope(u64),
+    /// A Tokio task, outside of any [`scope`].
+    #[cfg(feature = "async")]
+    Task(tokio::task::Id),
+}
+
+/// A lock taken in re-entrant mode.
+#[must_use = "if unused the RwLock will immediately unlock"]
+#[derive(Debug)]
+pub struct ReentrantGuard<T: AsOpenFile> {
+    file: Option<T>,
+    id: FileId,
+    owner: Owner,
+}
+
+pub type ReentrantResult<T> = Result<ReentrantGuard<T>, LockError<T>>;
+
+struct Entry {
+    id: FileId,
+    owner: Owner,
+    depth: usize,
+    lock: OsLock,
+}
+
+/// The OS lock behind the guards of an owner, held through a duplicate of
+/// the handle of the first guard.
+enum OsLock {
+    Read(RwLockReadGuard<File>),
+    Write(RwLockWriteGuard<File>),
+}
+
+impl Reentrant {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the mechanism used to take the OS lock.
+    pub fn strategy(mut self, strategy: LockStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Takes a shared lock, or nests in a lock the current owner holds.
+    pub fn lock_read<T: AsOpenFile>(&self, file: T) -> ReentrantResult<T> {
+        self.acquire::<false, true, _>(file)
+    }
+
+    /// Like [`Reentrant::lock_read`], but fails with
+    /// [`ErrorKind::WouldBlock`] instead of waiting.
+    pub fn try_lock_read<T: AsOpenFile>(&self, file: T) -> ReentrantResult<T> {
+        self.acquire::<false, false, _>(file)
+    }
+
+    /// Takes an exclusive lock, or nests in an exclusive lock the current
+    /// owner holds.
+    ///
+    /// Fails with [`ErrorKind::Deadlock`] if the current owner holds a shared
+    /// lock on the file, which cannot be upgraded.
+    pub fn lock_write<T: AsOpenFile>(&self, file: T) -> ReentrantResult<T> {
+        self.acquire::<true, true, _>(file)
+    }
+
+    /// Like [`Reentrant::lock_write`], but fails with
+    /// [`ErrorKind::WouldBlock`] instead of waiting.
+    pub fn try_lock_write<T: AsOpenFile>(&self, file: T) -> ReentrantResult<T> {
+        self.acquire::<true, false, _>(file)
+    }
+
+    /// Like [`Reentrant::lock_read`], but waits on a blocking task.
+    #[cfg(feature = "async")]
+    pub async fn lock_read_async<T: AsOpenFile>(&self, file: T) -> ReentrantResult<T> {
+        self.acquire_async::<false, _>(file).await
+    }
+
+    /// Like [`Reentrant::lock_write`], but waits on a blocking task.
+    #[cfg(feature = "async")]
+    pub async fn lock_write_async<T: AsOpenFile>(&self, file: T) -> ReentrantResult<T> {
+        self.acquire_async::<true, _>(file).await
+    }
+
+    fn acquire<const WRITE: bool, const BLOCK: bool, T: AsOpenFile>(
+        &self,
+        file: T,
+    ) -> ReentrantResult<T> {
+        let owner = Owner::current();
+        let result = nest::<WRITE, _>(&file, owner).and_then(|nested| match nested {
+            Some(id) => Ok((id, None)),
+            None => {
+                let lock =
+                    OsLock::acquire::<WRITE, BLOCK>(duplicate(&file)?, self.strategy, Some(owner))?;
+                Ok((file_id(&file)?, Some(lock)))
+            }
+        });
+        match result {
+            Ok((id, lock)) => Ok(ReentrantGuard::register(file, id, owner, lock)),
+            Err(error) => Err(LockError::new(file, error)),
+        }
+    }
+
+    #[cfg(feature = "async")]
+    async fn acquire_async<const WRITE: bool, T: AsOpenFile>(&self, file: T) -> ReentrantResult<T> {
+        let owner = Owner::current();
+        let id = match nest::<WRITE, _>(&file, owner) {
+            Ok(Some(id)) => return Ok(ReentrantGuard::register(file, id, owner, None)),
+            Ok(None) => file_id(&file),
+            Err(error) => Err(error),
+        };
+        let handle = id.and_then(|id| Ok((id, duplicate(&file)?)));
+        let (id, handle) = match handle {
+            Ok(handle) => handle,
+            Err(error) => return Err(LockError::new(file, error)),
+        };
+        let strategy = self.strategy;
+        // Outside of a scope, the owner is shared with other futures.
+        let scoped = Owner::scoped();
+        let lock = tokio::task::spawn_blocking(move || {
+            OsLock::acquire::<WRITE, true>(handle, strategy, scoped)
+        })
+        .await;
+        let lock = match lock {
+            Ok(lock) => lock,
+            Err(error) if error.is_panic() => std::panic::resume_unwind(error.into_panic()),
+            Err(_) => unreachable!("the blocking task is not cancelable"),
+        };
+        match lock {
+            Ok(lock) => Ok(ReentrantGuard::register(file, id, owner, Some(lock))),
+            Err(error) => Err(LockError::new(file, error)),
+        }
+    }
+}
+
+impl Owner {
+    /// Returns the current [`scope`], or outside of one, the current Tokio
+    /// task, or outside of any task, the current thread.
+    ///
+    /// All futures polled by the same task share its owner, so futures that
+    /// are joined or selected within one task nest in each other's locks
+    /// instead of excluding each other. Run each of them in its own [`scope`]
+    /// to keep them apart. Likewise, futures passed to `block_on` are owned by
+    /// the thread that blocks on them.
+    pub fn current() -> Self {
+        #[cfg(feature = "async")]
+        if let Ok(scope) = SCOPE.try_with(|scope| *scope) {
+            return Self::Scope(scope);
+        }
+        #[cfg(feature = "async")]
+        if let Some(task) = tokio::task::try_id() {
+            return Self::Task(task);
+        }
+        Self::Thread(thread::current().id())
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "async")] {
+        use std::future::Future;
+        use std::sync::atomic::{AtomicU64, Ordering};
+
+        tokio::task_local! {
+            static SCOPE: u64;
+        }
+
+        /// Runs `future` as a new [`Owner`], so that the re-entrant locks it
+        /// takes belong to it, whichever thread it runs on.
+        pub async fn scope<F: Future>(future: F) -> F::Output {
+            static NEXT: AtomicU64 = AtomicU64::new(0);
+            SCOPE.scope(NEXT.fetch_add(1, Ordering::Relaxed), future).await
+        }
+
+        impl Owner {
+            /// Returns the current [`scope`], if any.
+            pub(crate) fn scoped() -> Option<Self> {
+                SCOPE.try_with(|scope| Self::Scope(*scope)).ok()
+            }
+        }
+    }
+}
+
+/// Nests in the lock the owner holds on `file`, returning its ID, or `None` if
+/// the owner holds no lock on it.
+fn nest<const WRITE: bool, T: AsOpenFile>(file: &T, owner: Owner) -> io::Result<Option<FileId>> {
+    let id = file_id(file)?;
+    let mut held = HELD.lock().unwrap_or_else(PoisonError::into_inner);
+    let Some(entry) = held
+        .iter_mut()
+        .find(|entry| entry.id == id && entry.owner == owner)
+    else {
+        return Ok(None);
+    };
+    if WRITE && matches!(entry.lock, OsLock::Read(_)) {
+        return Err(io::Error::new(
+            ErrorKind::Deadlock,
+            "cannot upgrade a re-entrant shared lock to an exclusive one",
+        ));
+    }
+    entry.depth += 1;
+    Ok(Some(id))
+}
+
+impl OsLock {
+    fn acquire<const WRITE: bool, const BLOCK: bool>(
+        handle: File,
+        strategy: LockStrategy,
+        owner: Option<Owner>,
+    ) -> io::Result<Self> {
+        let guard = acquire_as::<WRITE, BLOCK, _>(&handle, strategy, owner)?;
+        Ok(match WRITE {
+            false => Self::Read(RwLockReadGuard::new(handle, guard)),
+            true => Self::Write(RwLockWriteGuard::new(handle, guard)),
+        })
+    }
+
+    fn release(self) -> io::Result<()> {
+        match self {
+            Self::Read(guard) => guard.release().map(drop),
+            Self::Write(guard) => guard.release().map(drop),
+        }
+    }
+}
+
+impl<T: AsOpenFile> ReentrantGuard<T> {
+    /// Creates a guard, registering `lock` if this is the outermost guard.
+    fn register(file: T, id: FileId, owner: Owner, lock: Option<OsLock>) -> Self {
+        if let Some(lock) = lock {
+            let mut held = HELD.lock().unwrap_or_else(PoisonError::into_inner);
+            match held
+                .iter_mut()
+                .find(|entry| entry.id == id && entry.owner == owner)
+            {
+                // Another future of the same scope won the race.
+                Some(entry) => {
+                    entry.depth += 1;
+                    drop(held);
+                    let _ = lock.release();
+                }
+                None => held.push(Entry {
+                    id,
+                    owner,
+                    depth: 1,
+                    lock,
+                }),
+            }
+        }
+        Self {
+            file: Some(file),
+            id,
+            owner,
+        }
+    }
+
+    /// Returns the owner holding the lock.
+    pub fn owner(&self) -> Owner {
+        self.owner
+    }
+
+    pub fn inner(&self) -> &T {
+        self.file
+            .as_ref()
+            .expect("file only removed during release")
+    }
+
+    pub fn inner_mut(&mut self) -> &mut T {
+        self.file
+            .as_mut()
+            .expect("file only removed during release")
+    }
+
+    /// Releases this guard, returning the inner file. The OS lock is released
+    /// if this was the last guard of its owner on the file.
+    pub fn release(mut self) -> io::Result<T> {
+        let file = self.file.take().expect("file only removed during release");
+        self.unregister()?;
+        Ok(file)
+    }
+
+    fn unregister(&self) -> io::Result<()> {
+        let mut held = HELD.lock().unwrap_or_else(PoisonError::into_inner);
+        let index = held
+            .iter()
+            .position(|entry| entry.id == self.id && entry.owner == self.owner)
+            .expect("guards are registered until dropped");
+        held[index].depth -= 1;
+        if held[index].depth > 0 {
+            return Ok(());
+        }
+        let entry = held.swap_remove(index);
+        drop(held);
+        entry.lock.release()
+    }
+}
+
+impl<T: AsOpenFile> Drop for ReentrantGuard<T> {
+    fn drop(&mut self) {
+        if self.file.is_some() {
+            let _ = self.unregister();
+        }
+    }
+}
+
+/// Delegate [`Read`] to the inner file.
+impl<T: AsOpenFile + Read> Read for ReentrantGuard<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner_mut().read(buf)
+    }
+}
+
+/// Delegate [`Write`] to the inner file.
+impl<T: AsOpenFile + Write> Write for ReentrantGuard<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner_mut().flush()
+    }
+}
+
+/// Delegate [`Seek`] to the inner file.
+impl<T: AsOpenFile + Seek> Seek for ReentrantGuard<T> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.inner_mut().seek(pos)
+    }
+}
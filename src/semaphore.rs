@@ -0,0 +1,183 @@
+//! Counting semaphores shared between processes.
+//!
+//! A [`FileSemaphore`] with `N` slots is a directory of slot files named
+//! `slot-0` to `slot-<N - 1>`. Acquiring the semaphore takes an exclusive lock
+//! on any free slot file, so at most `N` holders exist at once, and slots of
+//! crashed holders are freed along with their locks. Waiting polls the slots
+//! with exponential backoff.
+//!
+//! # Example
+//!
+//! ```
+//! use std::io::ErrorKind;
+//! use async_fd_lock::semaphore::FileSemaphore;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let semaphore = FileSemaphore::new(dir.path(), 2);
+//!
+//! let first = semaphore.acquire()?;
+//! let second = semaphore.acquire()?;
+//! assert_ne!(first.slot(), second.slot());
+//! assert_eq!(semaphore.try_acquire().unwrap_err().kind(), ErrorKind::WouldBlock);
+//!
+//! first.release()?;
+//! assert!(semaphore.try_acquire().is_ok());
+//! # std::io::Result::Ok(())
+//! ```
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use crate::blocking::LockWrite;
+use crate::{LockStrategy, RwLockWriteGuard};
+
+/// A semaphore with a fixed number of slots, backed by slot files in a
+/// directory.
+#[derive(Debug, Clone)]
+pub struct FileSemaphore {
+    dir: PathBuf,
+    slots: usize,
+    strategy: LockStrategy,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+/// A slot of a [`FileSemaphore`], held until the guard is dropped or
+/// released.
+#[must_use = "if unused the slot will immediately be freed"]
+#[derive(Debug)]
+pub struct SemaphoreGuard {
+    slot: usize,
+    guard: RwLockWriteGuard<File>,
+}
+
+impl FileSemaphore {
+    /// Creates a semaphore with `slots` slots, backed by slot files in `dir`.
+    ///
+    /// The directory is created when the semaphore is first acquired. All
+    /// users of the directory must agree on the number of slots.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `slots` is zero.
+    pub fn new(dir: impl Into<PathBuf>, slots: usize) -> Self {
+        assert!(slots > 0, "a semaphore needs at least one slot");
+        Self {
+            dir: dir.into(),
+            slots,
+            strategy: LockStrategy::default(),
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(1),
+        }
+    }
+
+    /// Sets the mechanism used to lock the slot files.
+    pub fn strategy(mut self, strategy: LockStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Sets how long waiting starts out sleeping between attempts, and the
+    /// longest it sleeps after doubling the interval on every attempt.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn slots(&self) -> usize {
+        self.slots
+    }
+
+    /// Returns the path of the slot file for `slot`.
+    pub fn slot_path(&self, slot: usize) -> PathBuf {
+        self.dir.join(format!("slot-{slot}"))
+    }
+
+    /// Takes a free slot, failing with [`ErrorKind::WouldBlock`] if all slots
+    /// are held.
+    pub fn try_acquire(&self) -> io::Result<SemaphoreGuard> {
+        fs::create_dir_all(&self.dir)?;
+        // Start at a different slot in every process, so that contenders do
+        // not all race for the first one.
+        let start = std::process::id() as usize % self.slots;
+        for slot in (start..self.slots).chain(0..start) {
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(self.slot_path(slot))?;
+            match file.try_lock_write_with(self.strategy) {
+                Ok(guard) => return Ok(SemaphoreGuard { slot, guard }),
+                Err(error) if error.error.kind() == ErrorKind::WouldBlock => {}
+                Err(error) => return Err(error.error),
+            }
+        }
+        Err(ErrorKind::WouldBlock.into())
+    }
+
+    /// Takes a free slot, waiting for one to be freed if all are held.
+    pub fn acquire(&self) -> io::Result<SemaphoreGuard> {
+        let mut backoff = self.initial_backoff;
+        loop {
+            match self.try_acquire() {
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {
+                    thread::sleep(backoff);
+                    backoff = (backoff * 2).min(self.max_backoff);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Like [`FileSemaphore::acquire`], but sleeps on the runtime between
+    /// attempts.
+    #[cfg(feature = "async")]
+    pub async fn acquire_async(&self) -> io::Result<SemaphoreGuard> {
+        let mut backoff = self.initial_backoff;
+        loop {
+            match self.try_acquire_async().await {
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(self.max_backoff);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Like [`FileSemaphore::try_acquire`], but opens the slot files on a
+    /// blocking task.
+    #[cfg(feature = "async")]
+    pub async fn try_acquire_async(&self) -> io::Result<SemaphoreGuard> {
+        let semaphore = self.clone();
+        tokio::task::spawn_blocking(move || semaphore.try_acquire())
+            .await
+            .expect("the blocking task is not cancelable")
+    }
+}
+
+impl SemaphoreGuard {
+    /// Returns the index of the slot held by this guard.
+    pub fn slot(&self) -> usize {
+        self.slot
+    }
+
+    /// Returns the mechanism used to hold the slot.
+    pub fn strategy(&self) -> LockStrategy {
+        self.guard.strategy()
+    }
+
+    /// Frees the slot.
+    pub fn release(self) -> io::Result<()> {
+        self.guard.release().map(drop)
+    }
+}
@@ -0,0 +1,239 @@
+use std::io;
+
+#[cfg(unix)]
+use crate::broker::{Client, Grant};
+use crate::deadlock::{self, Hold};
+use crate::dotlock::{DotLock, DotLockFile};
+#[cfg(unix)]
+use crate::fork::{self, Tracked};
+use crate::hierarchy::{self, Ranked};
+use crate::holder::Sidecar;
+use crate::reentrant::Owner;
+use crate::sys::{detect_strategy, path_of, AsOpenFile, AsOpenFileExt, RwLockGuard};
+
+/// The mechanism used to lock a file.
+///
+/// Pass it to the `*_with` methods of [`LockRead`](crate::LockRead) and
+/// [`LockWrite`](crate::LockWrite), or their [`blocking`](crate::blocking)
+/// counterparts. Guards report the mechanism that was actually used through
+/// their `strategy` method, which never returns [`LockStrategy::Auto`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum LockStrategy {
+    /// `flock(2)` on Unix and `LockFileEx` on Windows.
+    #[default]
+    Flock,
+    /// Open file description locks (`F_OFD_SETLK`).
+    ///
+    /// Unlike `flock`, these are forwarded to the server by NFS and SMB clients.
+    /// Only supported on Linux: elsewhere, `fcntl(2)` only offers record locks,
+    /// which are owned by the process and released when it closes any handle
+    /// to the file, so acquiring the lock fails with
+    /// [`ErrorKind::Unsupported`](io::ErrorKind::Unsupported).
+    Fcntl,
+    /// A `<path>.lock` file, see [`DotLock`].
+    ///
+    /// Lock files are always exclusive, so shared locks taken with this
+    /// strategy exclude each other. Only supported on Linux, where the path of
+    /// an open file can be recovered.
+    DotLock,
+    /// A lock granted by the broker listening on
+    /// [`default_socket_path`](crate::broker::default_socket_path), see
+    /// [`broker`](crate::broker).
+    ///
+    /// The lock is named after the path of the file, so this is only
+    /// supported on Linux, where the path of an open file can be recovered.
+    Broker,
+    /// Picks one of the other strategies based on the filesystem the file
+    /// resides on.
+    ///
+    /// On Linux, NFS and SMB mounts use [`LockStrategy::Fcntl`], FUSE mounts
+    /// use [`LockStrategy::DotLock`] and everything else, including tmpfs and
+    /// overlayfs, uses [`LockStrategy::Flock`]. Other platforms always use
+    /// [`LockStrategy::Flock`].
+    Auto,
+}
+
+/// The lock held by a guard, and the holder record published for it.
+#[derive(Debug)]
+pub(crate) struct Held {
+    kind: HeldKind,
+    // Boxed, since few locks publish their holder.
+    sidecar: Option<Box<Sidecar>>,
+    /// The record of the lock in the wait-for graph, removed after the lock
+    /// is released.
+    hold: Option<Hold>,
+    /// The record of the lock among those held at some level.
+    ranked: Option<Ranked>,
+    /// The process that acquired the lock.
+    #[cfg(unix)]
+    fork: Tracked,
+}
+
+#[derive(Debug)]
+enum HeldKind {
+    Os(LockStrategy),
+    DotLock(DotLockFile),
+    #[cfg(unix)]
+    Broker(Grant),
+}
+
+impl LockStrategy {
+    fn resolve<T: AsOpenFile>(self, file: &T) -> io::Result<Self> {
+        match self {
+            Self::Auto => detect_strategy(file),
+            strategy => Ok(strategy),
+        }
+    }
+}
+
+impl Held {
+    pub(crate) fn os(strategy: LockStrategy) -> Self {
+        Self {
+            kind: HeldKind::Os(strategy),
+            sidecar: None,
+            hold: None,
+            ranked: None,
+            #[cfg(unix)]
+            fork: Tracked::new(),
+        }
+    }
+
+    pub(crate) fn dotlock(lock_file: DotLockFile) -> Self {
+        Self {
+            kind: HeldKind::DotLock(lock_file),
+            sidecar: None,
+            hold: None,
+            ranked: None,
+            #[cfg(unix)]
+            fork: Tracked::new(),
+        }
+    }
+
+    #[cfg(unix)]
+    pub(crate) fn broker(grant: Grant) -> Self {
+        Self {
+            kind: HeldKind::Broker(grant),
+            sidecar: None,
+            hold: None,
+            ranked: None,
+            #[cfg(unix)]
+            fork: Tracked::new(),
+        }
+    }
+
+    pub(crate) fn strategy(&self) -> LockStrategy {
+        match &self.kind {
+            HeldKind::Os(strategy) => *strategy,
+            HeldKind::DotLock(_) => LockStrategy::DotLock,
+            #[cfg(unix)]
+            HeldKind::Broker(_) => LockStrategy::Broker,
+        }
+    }
+
+    /// Replaces the published holder record, removing the previous one.
+    pub(crate) fn set_sidecar(&mut self, sidecar: Sidecar) -> io::Result<()> {
+        let path = sidecar.path().to_owned();
+        match self.sidecar.replace(Box::new(sidecar)) {
+            // A record at the same path was overwritten by the new one.
+            Some(previous) if previous.path() != path => previous.remove(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Records that the lock is held through `file`. Only locks held by the
+    /// OS are kept alive by the descriptor.
+    pub(crate) fn track<T: AsOpenFile>(&self, file: &T) {
+        #[cfg(unix)]
+        if let HeldKind::Os(_) = self.kind {
+            self.fork.track(file);
+        }
+        #[cfg(not(unix))]
+        let _ = file;
+    }
+
+    /// Removes the holder record, then releases the lock, so that the record
+    /// never outlives the lock.
+    pub(crate) fn release<T: AsOpenFile>(&self, file: &T) -> io::Result<()> {
+        // Releasing a lock inherited across `fork` would release it for the
+        // parent too.
+        #[cfg(unix)]
+        if self.fork.is_inherited() {
+            return Ok(());
+        }
+        let removed = self.sidecar.as_deref().map_or(Ok(()), Sidecar::remove);
+        let released = match &self.kind {
+            HeldKind::Os(strategy) => file.release_lock_blocking(*strategy),
+            HeldKind::DotLock(lock_file) => lock_file.remove(),
+            #[cfg(unix)]
+            HeldKind::Broker(grant) => grant.release(),
+        };
+        removed.and(released)
+    }
+
+    /// Gives up the lock without releasing it, for another process to hold.
+    /// The holder record names this process, so it is removed.
+    #[cfg(unix)]
+    pub(crate) fn hand_over(self) {
+        if let Some(sidecar) = &self.sidecar {
+            let _ = sidecar.remove();
+        }
+    }
+}
+
+/// Locks `file` using `strategy` on behalf of the current owner, returning a
+/// guard that owns a duplicate of its handle.
+pub(crate) fn acquire<const WRITE: bool, const BLOCK: bool, T: AsOpenFile>(
+    file: &T,
+    strategy: LockStrategy,
+) -> io::Result<RwLockGuard<<T as AsOpenFileExt>::OwnedOpenFile>> {
+    acquire_as::<WRITE, BLOCK, _>(file, strategy, Some(Owner::current()))
+}
+
+/// Like [`acquire`], but on behalf of `owner`, for acquisitions made on a
+/// blocking task. Without an owner, the lock is left out of the wait-for
+/// graph and its level is not checked.
+pub(crate) fn acquire_as<const WRITE: bool, const BLOCK: bool, T: AsOpenFile>(
+    file: &T,
+    strategy: LockStrategy,
+    owner: Option<Owner>,
+) -> io::Result<RwLockGuard<<T as AsOpenFileExt>::OwnedOpenFile>> {
+    #[cfg(unix)]
+    fork::prepare(file)?;
+    let (ranked, acquisition) = match owner {
+        Some(owner) => (
+            hierarchy::enter(file, owner)?,
+            deadlock::begin::<WRITE, BLOCK, _>(file, owner)?,
+        ),
+        None => (None, None),
+    };
+    let handle_clone = file.borrow_open_file().try_clone_to_owned()?;
+    let mut held = match strategy.resolve(file)? {
+        LockStrategy::DotLock => {
+            Held::dotlock(DotLock::new(path_of(file)?).acquire_blocking::<BLOCK>()?)
+        }
+        #[cfg(unix)]
+        LockStrategy::Broker => Held::broker(Client::from_env().acquire::<WRITE, BLOCK, _>(file)?),
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        LockStrategy::Fcntl => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "open file description locks are only supported on Linux",
+            ))
+        }
+        #[cfg(not(unix))]
+        LockStrategy::Broker => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "lock brokers are only supported on Unix",
+            ))
+        }
+        strategy => {
+            file.acquire_lock_blocking::<WRITE, BLOCK>(strategy)?;
+            Held::os(strategy)
+        }
+    };
+    held.hold = acquisition.map(deadlock::Acquisition::hold);
+    held.ranked = ranked;
+    Ok(RwLockGuard::new(handle_clone, held))
+}
@@ -2,16 +2,40 @@ use std::io;
 
 use cfg_if::cfg_if;
 
+use crate::strategy::{Held, LockStrategy};
+
 cfg_if! {
     if #[cfg(unix)] {
         mod unix;
 
+        pub(crate) use unix::{detect_strategy, file_id, hostname, path_of, process_exists};
         pub use rustix::fd::AsFd as AsOpenFile;
+        pub(crate) type OwnedOpenFile = rustix::fd::OwnedFd;
     } else if #[cfg(windows)] {
         mod windows;
 
+        pub(crate) use windows::{detect_strategy, file_id, hostname, path_of, process_exists};
         #[doc(no_inline)]
         pub use std::os::windows::io::AsHandle as AsOpenFile;
+        pub(crate) type OwnedOpenFile = std::os::windows::io::OwnedHandle;
+    }
+}
+
+/// Identifies the file behind an open file, regardless of which handle refers to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct FileId {
+    pub(crate) device: u64,
+    pub(crate) index: u64,
+}
+
+/// Duplicates the handle of `file` into a standalone [`std::fs::File`].
+pub(crate) fn duplicate<T: AsOpenFile>(file: &T) -> io::Result<std::fs::File> {
+    cfg_if! {
+        if #[cfg(unix)] {
+            Ok(file.as_fd().try_clone_to_owned()?.into())
+        } else if #[cfg(windows)] {
+            Ok(file.as_handle().try_clone_to_owned()?.into())
+        }
     }
 }
 
@@ -21,48 +45,50 @@ pub(crate) trait AsOpenFileExt: AsOpenFile {
         Self: 'a;
     type OwnedOpenFile: AsOpenFile;
 
-    cfg_if! {
-        if #[cfg(feature = "async")] {
-            fn borrow_open_file(&self) -> Self::BorrowedOpenFile<'_>;
-        } else {
-            #[allow(unused)]
-            fn borrow_open_file(&self) -> Self::BorrowedOpenFile<'_>;
-        }
-    }
+    fn borrow_open_file(&self) -> Self::BorrowedOpenFile<'_>;
 
     fn acquire_lock_blocking<const WRITE: bool, const BLOCK: bool>(
         &self,
-    ) -> io::Result<RwLockGuard<Self::OwnedOpenFile>>
+        strategy: LockStrategy,
+    ) -> io::Result<()>
     where
         Self: Sized;
-    fn release_lock_blocking(&self) -> io::Result<()>;
+    fn release_lock_blocking(&self, strategy: LockStrategy) -> io::Result<()>;
 }
 
 #[must_use = "if unused the RwLock will immediately unlock"]
 pub struct RwLockGuard<T: AsOpenFile> {
-    handle: Option<<T as AsOpenFileExt>::OwnedOpenFile>,
+    lock: Option<(<T as AsOpenFileExt>::OwnedOpenFile, Held)>,
 }
 
 impl<T: AsOpenFile> RwLockGuard<T> {
-    pub fn new(handle: <T as AsOpenFileExt>::OwnedOpenFile) -> Self {
+    pub fn new(handle: <T as AsOpenFileExt>::OwnedOpenFile, held: Held) -> Self {
+        held.track(&handle);
         Self {
-            handle: Some(handle),
+            lock: Some((handle, held)),
         }
     }
 
-    pub fn defuse(mut self) -> <T as AsOpenFileExt>::OwnedOpenFile {
-        self.handle.take().expect("handle should always be present")
+    pub fn held(&self) -> &Held {
+        let (_, held) = self.lock.as_ref().expect("handle should always be present");
+        held
+    }
+
+    pub fn defuse(mut self) -> Held {
+        let (_, held) = self.lock.take().expect("handle should always be present");
+        held
     }
 
-    pub fn defuse_with<R>(self, map: impl FnOnce(<T as AsOpenFileExt>::OwnedOpenFile) -> R) -> R {
-        (map)(self.defuse())
+    pub fn release(mut self) -> io::Result<()> {
+        let (handle, held) = self.lock.take().expect("handle should always be present");
+        held.release(&handle)
     }
 }
 
 impl<T: AsOpenFile> Drop for RwLockGuard<T> {
     fn drop(&mut self) {
-        if let Some(handle) = self.handle.take() {
-            let _ = handle.release_lock_blocking();
+        if let Some((handle, held)) = self.lock.take() {
+            let _ = held.release(&handle);
         }
     }
 }
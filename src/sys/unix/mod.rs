@@ -2,18 +2,95 @@ mod utils;
 
 use rustix::fd::{BorrowedFd, OwnedFd};
 use rustix::fs::FlockOperation;
+use rustix::io::Errno;
+use rustix::process::{test_kill_process, Pid};
 use std::io::{self, Error, ErrorKind};
+use std::path::PathBuf;
 use utils::*;
 
 use crate::sys::{AsOpenFile, AsOpenFileExt};
+use crate::LockStrategy;
 
-use super::RwLockGuard;
+use super::FileId;
+
+#[allow(clippy::unnecessary_cast)]
+pub(crate) fn file_id<T: AsOpenFile>(file: &T) -> io::Result<FileId> {
+    let stat = rustix::fs::fstat(file)?;
+    Ok(FileId {
+        device: stat.st_dev as u64,
+        index: stat.st_ino as u64,
+    })
+}
+
+pub(crate) fn hostname() -> String {
+    rustix::system::uname()
+        .nodename()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Returns whether a process with the given ID is running, if that can be
+/// determined.
+pub(crate) fn process_exists(pid: u32) -> Option<bool> {
+    let pid = Pid::from_raw(pid.try_into().ok()?)?;
+    match test_kill_process(pid) {
+        Err(Errno::SRCH) => Some(false),
+        // `EPERM` means that the process exists, but belongs to someone else.
+        _ => Some(true),
+    }
+}
+
+/// Picks a lock strategy suited to the filesystem `file` resides on.
+pub(crate) fn detect_strategy<T: AsOpenFile>(file: &T) -> io::Result<LockStrategy> {
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_os = "linux", target_os = "android"))] {
+            const NFS_SUPER_MAGIC: u32 = 0x6969;
+            const SMB_SUPER_MAGIC: u32 = 0x517b;
+            const SMB2_MAGIC_NUMBER: u32 = 0xfe53_4d42;
+            const CIFS_MAGIC_NUMBER: u32 = 0xff53_4d42;
+            const FUSE_SUPER_MAGIC: u32 = 0x6573_5546;
+
+            // `f_type` differs in width and signedness between platforms.
+            #[allow(clippy::unnecessary_cast)]
+            let magic = rustix::fs::fstatfs(file)?.f_type as u32;
+            Ok(match magic {
+                NFS_SUPER_MAGIC | SMB_SUPER_MAGIC | SMB2_MAGIC_NUMBER | CIFS_MAGIC_NUMBER => {
+                    LockStrategy::Fcntl
+                }
+                FUSE_SUPER_MAGIC => LockStrategy::DotLock,
+                // Including tmpfs and overlayfs, which support `flock`.
+                _ => LockStrategy::Flock,
+            })
+        } else {
+            let _ = file;
+            Ok(LockStrategy::Flock)
+        }
+    }
+}
+
+/// Returns the path `file` was opened at.
+pub(crate) fn path_of<T: AsOpenFile>(file: &T) -> io::Result<PathBuf> {
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_os = "linux", target_os = "android"))] {
+            use rustix::fd::AsRawFd;
+
+            std::fs::read_link(format!("/proc/self/fd/{}", file.as_fd().as_raw_fd()))
+        } else {
+            let _ = file;
+            Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "the path of an open file cannot be determined on this platform",
+            ))
+        }
+    }
+}
 
 impl<T> AsOpenFileExt for T
 where
     T: AsOpenFile,
 {
-    type BorrowedOpenFile<'a> = BorrowedFd<'a>
+    type BorrowedOpenFile<'a>
+        = BorrowedFd<'a>
     where
         Self: 'a;
     type OwnedOpenFile = OwnedFd;
@@ -24,8 +101,12 @@ where
 
     fn acquire_lock_blocking<const WRITE: bool, const BLOCK: bool>(
         &self,
-    ) -> io::Result<RwLockGuard<Self::OwnedOpenFile>> {
-        let handle_clone = self.as_fd().try_clone_to_owned()?;
+        strategy: LockStrategy,
+    ) -> io::Result<()> {
+        #[cfg(feature = "testing")]
+        if let Some(result) = crate::testing::simulator::acquire::<WRITE, BLOCK>(self) {
+            return result;
+        }
         let operation = match (WRITE, BLOCK) {
             (false, false) => FlockOperation::NonBlockingLockShared,
             (false, true) => FlockOperation::LockShared,
@@ -33,21 +114,33 @@ where
             (true, true) => FlockOperation::LockExclusive,
         };
         let fd = self.as_fd();
-        let result = compatible_unix_lock(fd, operation);
+        let result = match strategy {
+            LockStrategy::Fcntl => fcntl_lock(fd, operation),
+            _ => compatible_unix_lock(fd, operation),
+        };
         if BLOCK {
             result?;
         } else {
-            result.map_err(|err| match err.kind() {
-                ErrorKind::AlreadyExists => ErrorKind::WouldBlock.into(),
+            result.map_err(|err| match err {
+                // `fcntl` reports conflicting record locks with `EACCES`.
+                Errno::ACCESS if strategy == LockStrategy::Fcntl => ErrorKind::WouldBlock.into(),
+                _ if err.kind() == ErrorKind::AlreadyExists => ErrorKind::WouldBlock.into(),
                 _ => Error::from(err),
             })?;
         }
-        Ok(RwLockGuard::new(handle_clone))
+        Ok(())
     }
 
-    fn release_lock_blocking(&self) -> io::Result<()> {
+    fn release_lock_blocking(&self, strategy: LockStrategy) -> io::Result<()> {
+        #[cfg(feature = "testing")]
+        if let Some(result) = crate::testing::simulator::release(self) {
+            return result;
+        }
         let fd = self.as_fd();
-        compatible_unix_lock(fd, FlockOperation::Unlock)?;
+        match strategy {
+            LockStrategy::Fcntl => fcntl_lock(fd, FlockOperation::Unlock)?,
+            _ => compatible_unix_lock(fd, FlockOperation::Unlock)?,
+        }
         Ok(())
     }
 }
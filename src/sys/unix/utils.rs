@@ -12,3 +12,44 @@ pub(crate) fn compatible_unix_lock<Fd: AsFd>(
     #[cfg(target_os = "solaris")]
     return fs::fcntl_lock(fd, operation);
 }
+
+/// Locks the whole file with an open file description lock where supported,
+/// falling back to process-associated record locks elsewhere.
+pub(crate) fn fcntl_lock<Fd: AsFd>(
+    fd: Fd,
+    operation: fs::FlockOperation,
+) -> rustix::io::Result<()> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    return ofd_lock(fd, operation);
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    return fs::fcntl_lock(fd, operation);
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn ofd_lock<Fd: AsFd>(fd: Fd, operation: fs::FlockOperation) -> rustix::io::Result<()> {
+    use fs::FlockOperation;
+    use rustix::fd::AsRawFd;
+
+    let (command, lock_type) = match operation {
+        FlockOperation::LockShared => (libc::F_OFD_SETLKW, libc::F_RDLCK),
+        FlockOperation::LockExclusive => (libc::F_OFD_SETLKW, libc::F_WRLCK),
+        FlockOperation::Unlock => (libc::F_OFD_SETLKW, libc::F_UNLCK),
+        FlockOperation::NonBlockingLockShared => (libc::F_OFD_SETLK, libc::F_RDLCK),
+        FlockOperation::NonBlockingLockExclusive => (libc::F_OFD_SETLK, libc::F_WRLCK),
+        FlockOperation::NonBlockingUnlock => (libc::F_OFD_SETLK, libc::F_UNLCK),
+    };
+    // SAFETY: `flock` is a plain C struct for which all zeroes is a valid value.
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = lock_type as _;
+    lock.l_whence = libc::SEEK_SET as _;
+    // A zero `l_start` and `l_len` cover the whole file, even as it grows.
+    // SAFETY: `fd` is a valid file descriptor and `lock` outlives the call.
+    match unsafe { libc::fcntl(fd.as_fd().as_raw_fd(), command, &lock) } {
+        -1 => Err(
+            rustix::io::Errno::from_io_error(&std::io::Error::last_os_error())
+                .unwrap_or(rustix::io::Errno::IO),
+        ),
+        _ => Ok(()),
+    }
+}
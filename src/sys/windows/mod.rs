@@ -2,22 +2,60 @@ mod utils;
 
 use std::io::{self, Error, ErrorKind};
 use std::os::windows::io::{AsRawHandle, BorrowedHandle, OwnedHandle};
+use std::path::PathBuf;
 use utils::{syscall, Overlapped};
 use windows_sys::Win32::Foundation::ERROR_LOCK_VIOLATION;
 use windows_sys::Win32::Foundation::HANDLE;
 use windows_sys::Win32::Storage::FileSystem::{
-    LockFileEx, UnlockFile, LOCKFILE_EXCLUSIVE_LOCK, LOCKFILE_FAIL_IMMEDIATELY,
+    GetFileInformationByHandle, LockFileEx, UnlockFile, BY_HANDLE_FILE_INFORMATION,
+    LOCKFILE_EXCLUSIVE_LOCK, LOCKFILE_FAIL_IMMEDIATELY,
 };
 
 use crate::sys::{AsOpenFile, AsOpenFileExt};
+use crate::LockStrategy;
 
-use super::RwLockGuard;
+use super::FileId;
+
+pub(crate) fn file_id<T: AsOpenFile>(file: &T) -> io::Result<FileId> {
+    let handle = file.as_handle().as_raw_handle() as HANDLE;
+    let mut info: BY_HANDLE_FILE_INFORMATION = unsafe { std::mem::zeroed() };
+    syscall(unsafe { GetFileInformationByHandle(handle, &mut info) })?;
+    Ok(FileId {
+        device: info.dwVolumeSerialNumber as u64,
+        index: (info.nFileIndexHigh as u64) << 32 | info.nFileIndexLow as u64,
+    })
+}
+
+pub(crate) fn hostname() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_default()
+}
+
+/// Returns whether a process with the given ID is running, if that can be
+/// determined.
+pub(crate) fn process_exists(_pid: u32) -> Option<bool> {
+    None
+}
+
+/// Picks a lock strategy suited to the filesystem `file` resides on.
+pub(crate) fn detect_strategy<T: AsOpenFile>(_file: &T) -> io::Result<LockStrategy> {
+    // `LockFileEx` is forwarded to SMB servers, so it is always suitable.
+    Ok(LockStrategy::Flock)
+}
+
+/// Returns the path `file` was opened at.
+pub(crate) fn path_of<T: AsOpenFile>(_file: &T) -> io::Result<PathBuf> {
+    Err(io::Error::new(
+        ErrorKind::Unsupported,
+        "the path of an open file cannot be determined on this platform",
+    ))
+}
 
 impl<T> AsOpenFileExt for T
 where
     T: AsOpenFile,
 {
-    type BorrowedOpenFile<'a> = BorrowedHandle<'a>
+    type BorrowedOpenFile<'a>
+        = BorrowedHandle<'a>
     where
         Self: 'a;
     type OwnedOpenFile = OwnedHandle;
@@ -28,9 +66,13 @@ where
 
     fn acquire_lock_blocking<const WRITE: bool, const BLOCK: bool>(
         &self,
-    ) -> io::Result<RwLockGuard<Self::OwnedOpenFile>> {
+        _strategy: LockStrategy,
+    ) -> io::Result<()> {
         // See: https://stackoverflow.com/a/9186532, https://docs.microsoft.com/en-us/windows/win32/api/fileapi/nf-fileapi-lockfileex
-        let handle_clone = self.as_handle().try_clone_to_owned()?;
+        #[cfg(feature = "testing")]
+        if let Some(result) = crate::testing::simulator::acquire::<WRITE, BLOCK>(self) {
+            return result;
+        }
         let handle = self.as_handle().as_raw_handle() as HANDLE;
         let overlapped = Overlapped::zero();
         let flags = if WRITE { LOCKFILE_EXCLUSIVE_LOCK } else { 0 }
@@ -46,10 +88,14 @@ where
                 }
             })?;
         }
-        Ok(RwLockGuard::new(handle_clone))
+        Ok(())
     }
 
-    fn release_lock_blocking(&self) -> io::Result<()> {
+    fn release_lock_blocking(&self, _strategy: LockStrategy) -> io::Result<()> {
+        #[cfg(feature = "testing")]
+        if let Some(result) = crate::testing::simulator::release(self) {
+            return result;
+        }
         let handle = self.as_handle().as_raw_handle() as HANDLE;
         syscall(unsafe { UnlockFile(handle, 0, 0, 1, 0) })?;
         Ok(())
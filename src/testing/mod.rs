@@ -0,0 +1,8 @@
+//! Utilities for testing code that relies on file locks.
+//!
+//! Only available with the `testing` feature enabled.
+
+pub mod simulator;
+pub mod stress;
+
+pub use simulator::{Event, Fault, Hold, Simulator};
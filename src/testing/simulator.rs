@@ -0,0 +1,388 @@
+//! A simulated lock backend for deterministic tests.
+//!
+//! Once a [`Simulator`] is attached to a file, every lock acquired or released
+//! through this crate on that file (through any handle) is resolved against the
+//! simulator's state instead of the operating system. Locking still produces
+//! the regular [`RwLockReadGuard`](crate::RwLockReadGuard) and
+//! [`RwLockWriteGuard`](crate::RwLockWriteGuard) values, so code under test
+//! does not need to be aware of the simulation.
+//!
+//! # Example
+//!
+//! ```
+//! use std::io::ErrorKind;
+//! use async_fd_lock::blocking::LockWrite;
+//! use async_fd_lock::testing::{Fault, Simulator};
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let file = std::fs::File::create(dir.path().join("foo.txt")).unwrap();
+//! let simulator = Simulator::attach(&file).unwrap();
+//!
+//! // Pretend that another process holds the lock.
+//! let hold = simulator.hold_write().unwrap();
+//! let (file, error) = file.try_lock_write().unwrap_err().into();
+//! assert_eq!(error.kind(), ErrorKind::WouldBlock);
+//! hold.release();
+//!
+//! // Fail the next acquisition with `EINTR`.
+//! simulator.inject(Fault::Interrupted);
+//! let (file, error) = file.lock_write().unwrap_err().into();
+//! assert_eq!(error.kind(), ErrorKind::Interrupted);
+//!
+//! let _guard = file.lock_write().unwrap();
+//! ```
+
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError, Weak};
+
+use crate::sys::{file_id, AsOpenFile, FileId};
+
+/// Simulators currently attached to a file.
+static ATTACHED: Mutex<Vec<(FileId, Weak<Shared>)>> = Mutex::new(Vec::new());
+
+/// A simulated lock backend for a single file.
+///
+/// The simulation is active for as long as this value is alive.
+#[derive(Debug)]
+pub struct Simulator {
+    id: FileId,
+    shared: Arc<Shared>,
+}
+
+/// A failure to inject into a lock acquisition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Fault {
+    /// The acquisition was interrupted by a signal (`EINTR`).
+    Interrupted,
+    /// The system ran out of lock records (`ENOLCK`).
+    NoLocks,
+    /// A low-level I/O error occurred (`EIO`).
+    Io,
+}
+
+/// A step taken by the simulated backend, as recorded by [`Simulator::history`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Event {
+    /// A lock was acquired.
+    Acquired { write: bool },
+    /// A non-blocking acquisition failed because of a conflicting lock.
+    WouldBlock { write: bool },
+    /// An acquisition failed because of an injected [`Fault`].
+    Failed { write: bool, kind: ErrorKind },
+    /// A lock was released.
+    Released,
+}
+
+/// A lock held on behalf of a simulated external party, such as another
+/// process.
+///
+/// The lock is released when this value is dropped.
+#[must_use = "if unused the simulated lock will immediately unlock"]
+#[derive(Debug)]
+pub struct Hold {
+    shared: Arc<Shared>,
+    write: bool,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Shared locks held through this crate.
+    readers: usize,
+    /// Whether an exclusive lock is held through this crate.
+    writer: bool,
+    /// Shared locks held through [`Hold`].
+    held_readers: usize,
+    /// Whether an exclusive lock is held through [`Hold`].
+    held_writer: bool,
+    faults: VecDeque<Fault>,
+    paused: bool,
+    /// The ticket handed to the next acquisition queued while paused.
+    next_ticket: u64,
+    /// Tickets lower than this one have been let through.
+    admitted: u64,
+    /// Acquisitions waiting for a conflicting lock to be released.
+    blocked: usize,
+    history: Vec<Event>,
+}
+
+impl Simulator {
+    /// Routes all locking of the file behind `file` through a new simulator.
+    ///
+    /// Fails with [`ErrorKind::AlreadyExists`] if the file already has a
+    /// simulator attached.
+    pub fn attach<T: AsOpenFile>(file: &T) -> io::Result<Self> {
+        let id = file_id(file)?;
+        let mut attached = lock(&ATTACHED);
+        attached.retain(|(_, shared)| shared.strong_count() > 0);
+        if attached.iter().any(|(attached_id, _)| *attached_id == id) {
+            return Err(io::Error::new(
+                ErrorKind::AlreadyExists,
+                "a simulator is already attached to this file",
+            ));
+        }
+        let shared = Arc::new(Shared::default());
+        attached.push((id, Arc::downgrade(&shared)));
+        Ok(Self { id, shared })
+    }
+
+    /// Acquires a shared lock on behalf of a simulated external party.
+    ///
+    /// Fails with [`ErrorKind::WouldBlock`] if an exclusive lock is held.
+    pub fn hold_read(&self) -> io::Result<Hold> {
+        self.hold(false)
+    }
+
+    /// Acquires an exclusive lock on behalf of a simulated external party.
+    ///
+    /// Fails with [`ErrorKind::WouldBlock`] if any other lock is held.
+    pub fn hold_write(&self) -> io::Result<Hold> {
+        self.hold(true)
+    }
+
+    fn hold(&self, write: bool) -> io::Result<Hold> {
+        let mut state = self.shared.lock();
+        if state.conflicts(write) {
+            return Err(ErrorKind::WouldBlock.into());
+        }
+        if write {
+            state.held_writer = true;
+        } else {
+            state.held_readers += 1;
+        }
+        Ok(Hold {
+            shared: self.shared.clone(),
+            write,
+        })
+    }
+
+    /// Makes the next acquisition fail with `fault`.
+    ///
+    /// Faults are consumed in the order they were injected.
+    pub fn inject(&self, fault: Fault) {
+        self.shared.lock().faults.push_back(fault);
+    }
+
+    /// Makes new acquisitions wait in a queue until they are let through by
+    /// [`Simulator::step`] or [`Simulator::resume`].
+    pub fn pause(&self) {
+        self.shared.lock().paused = true;
+    }
+
+    /// Lets the oldest queued acquisition through.
+    ///
+    /// Returns `false` if no acquisition was queued.
+    pub fn step(&self) -> bool {
+        let mut state = self.shared.lock();
+        if state.admitted == state.next_ticket {
+            return false;
+        }
+        state.admitted += 1;
+        self.shared.changed.notify_all();
+        true
+    }
+
+    /// Lets all queued acquisitions through and stops queueing new ones.
+    pub fn resume(&self) {
+        let mut state = self.shared.lock();
+        state.paused = false;
+        state.admitted = state.next_ticket;
+        self.shared.changed.notify_all();
+    }
+
+    /// Returns the number of acquisitions queued by [`Simulator::pause`].
+    pub fn queued(&self) -> usize {
+        let state = self.shared.lock();
+        (state.next_ticket - state.admitted) as usize
+    }
+
+    /// Blocks the current thread until at least `count` acquisitions are queued.
+    pub fn wait_queued(&self, count: usize) {
+        let state = self.shared.lock();
+        let _state = self.shared.wait_while(state, |state| {
+            ((state.next_ticket - state.admitted) as usize) < count
+        });
+    }
+
+    /// Returns the number of acquisitions waiting for a conflicting lock to be
+    /// released.
+    pub fn blocked(&self) -> usize {
+        self.shared.lock().blocked
+    }
+
+    /// Blocks the current thread until at least `count` acquisitions are waiting
+    /// for a conflicting lock to be released.
+    pub fn wait_blocked(&self, count: usize) {
+        let state = self.shared.lock();
+        let _state = self.shared.wait_while(state, |state| state.blocked < count);
+    }
+
+    /// Returns every step taken by the simulated backend so far, oldest first.
+    pub fn history(&self) -> Vec<Event> {
+        self.shared.lock().history.clone()
+    }
+}
+
+impl Drop for Simulator {
+    fn drop(&mut self) {
+        lock(&ATTACHED).retain(|(id, _)| *id != self.id);
+        // Do not leave acquisitions stuck in the queue.
+        self.resume();
+    }
+}
+
+impl Hold {
+    /// Releases the simulated lock.
+    pub fn release(self) {}
+}
+
+impl Drop for Hold {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        if self.write {
+            state.held_writer = false;
+        } else {
+            state.held_readers -= 1;
+        }
+        self.shared.changed.notify_all();
+    }
+}
+
+impl From<Fault> for io::Error {
+    fn from(fault: Fault) -> Self {
+        cfg_if::cfg_if! {
+            if #[cfg(unix)] {
+                use rustix::io::Errno;
+
+                io::Error::from(match fault {
+                    Fault::Interrupted => Errno::INTR,
+                    Fault::NoLocks => Errno::NOLCK,
+                    Fault::Io => Errno::IO,
+                })
+            } else if #[cfg(windows)] {
+                use windows_sys::Win32::Foundation::{
+                    ERROR_IO_DEVICE, ERROR_OPERATION_ABORTED, ERROR_SHARING_BUFFER_EXCEEDED,
+                };
+
+                io::Error::from_raw_os_error(match fault {
+                    Fault::Interrupted => ERROR_OPERATION_ABORTED,
+                    Fault::NoLocks => ERROR_SHARING_BUFFER_EXCEEDED,
+                    Fault::Io => ERROR_IO_DEVICE,
+                } as i32)
+            }
+        }
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        lock(&self.state)
+    }
+
+    fn wait_while<'a>(
+        &self,
+        state: MutexGuard<'a, State>,
+        condition: impl FnMut(&mut State) -> bool,
+    ) -> MutexGuard<'a, State> {
+        self.changed
+            .wait_while(state, condition)
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn acquire(&self, write: bool, block: bool) -> io::Result<()> {
+        let mut state = self.lock();
+        if state.paused {
+            let ticket = state.next_ticket;
+            state.next_ticket += 1;
+            self.changed.notify_all();
+            state = self.wait_while(state, |state| state.admitted <= ticket);
+        }
+        if let Some(fault) = state.faults.pop_front() {
+            let error = io::Error::from(fault);
+            state.history.push(Event::Failed {
+                write,
+                kind: error.kind(),
+            });
+            return Err(error);
+        }
+        if state.conflicts(write) {
+            if !block {
+                state.history.push(Event::WouldBlock { write });
+                return Err(ErrorKind::WouldBlock.into());
+            }
+            state.blocked += 1;
+            self.changed.notify_all();
+            state = self.wait_while(state, |state| state.conflicts(write));
+            state.blocked -= 1;
+        }
+        if write {
+            state.writer = true;
+        } else {
+            state.readers += 1;
+        }
+        state.history.push(Event::Acquired { write });
+        Ok(())
+    }
+
+    fn release(&self) {
+        let mut state = self.lock();
+        // Shared and exclusive locks are never held at the same time, so the
+        // kind of lock being released is unambiguous.
+        if state.writer {
+            state.writer = false;
+        } else if state.readers > 0 {
+            state.readers -= 1;
+        } else {
+            return;
+        }
+        state.history.push(Event::Released);
+        self.changed.notify_all();
+    }
+}
+
+impl State {
+    fn conflicts(&self, write: bool) -> bool {
+        let writer = self.writer || self.held_writer;
+        let readers = self.readers + self.held_readers;
+        writer || (write && readers > 0)
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn attached<T: AsOpenFile>(file: &T) -> Option<Arc<Shared>> {
+    let attached = lock(&ATTACHED);
+    if attached.is_empty() {
+        return None;
+    }
+    let id = file_id(file).ok()?;
+    attached
+        .iter()
+        .find(|(attached_id, _)| *attached_id == id)
+        .and_then(|(_, shared)| shared.upgrade())
+}
+
+/// Resolves an acquisition against the attached simulator, if any.
+pub(crate) fn acquire<const WRITE: bool, const BLOCK: bool>(
+    file: &impl AsOpenFile,
+) -> Option<io::Result<()>> {
+    attached(file).map(|shared| shared.acquire(WRITE, BLOCK))
+}
+
+/// Resolves a release against the attached simulator, if any.
+pub(crate) fn release<T: AsOpenFile>(file: &T) -> Option<io::Result<()>> {
+    attached(file).map(|shared| {
+        shared.release();
+        Ok(())
+    })
+}
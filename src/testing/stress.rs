@@ -0,0 +1,469 @@
+//! A cross-process stress test harness.
+//!
+//! [`Stress::run`] spawns several copies of the current test binary, each of
+//! which repeatedly locks a shared file for reading or writing. Every worker
+//! logs when it enters and leaves a critical section to a shared journal, which
+//! the parent process replays afterwards to verify that writers were never
+//! concurrent with any other lock holder. Each run ends with a rendezvous, in
+//! which all workers must hold a shared lock at the same time, to verify that
+//! readers are not serialized.
+//!
+//! The workers lock the file with the [`LockStrategy`] set with
+//! [`Stress::strategy`]. With [`LockStrategy::Broker`], the parent process
+//! runs a broker for the workers, and with [`LockStrategy::DotLock`], whose
+//! shared locks exclude each other, the rendezvous is held without locks.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use async_fd_lock::testing::stress::{Backend, Stress};
+//!
+//! #[test]
+//! fn cross_process() {
+//!     // Inside of the spawned workers, this call never returns.
+//!     let report = Stress::new(Backend::Blocking)
+//!         .processes(4)
+//!         .iterations(100)
+//!         .run("cross_process")
+//!         .unwrap();
+//!     assert_eq!(report.writes, report.counter);
+//! }
+//! ```
+
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::blocking::{LockRead, LockWrite};
+use crate::LockStrategy;
+
+const WORKER_VAR: &str = "ASYNC_FD_LOCK_STRESS_WORKER";
+const DIR_VAR: &str = "ASYNC_FD_LOCK_STRESS_DIR";
+const ITERATIONS_VAR: &str = "ASYNC_FD_LOCK_STRESS_ITERATIONS";
+const HOLD_VAR: &str = "ASYNC_FD_LOCK_STRESS_HOLD_MICROS";
+
+const LOCK_FILE: &str = "lockfile";
+const JOURNAL_FILE: &str = "journal";
+#[cfg(unix)]
+const BROKER_SOCKET: &str = "broker.sock";
+/// The prefix of the file a failed worker writes its error to.
+const ERROR_FILE: &str = "error";
+
+/// How long workers wait for each other at a barrier.
+const BARRIER_TIMEOUT: Duration = Duration::from_secs(30);
+/// How often workers check whether the others reached a barrier.
+const BARRIER_POLL: Duration = Duration::from_millis(5);
+
+/// The API used by the workers to lock the shared file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Backend {
+    /// [`std::fs::File`] locked through [`crate::blocking`].
+    Blocking,
+    /// [`tokio::fs::File`] locked through [`crate::nonblocking`].
+    #[cfg(feature = "async")]
+    Async,
+}
+
+/// Configuration of a cross-process stress test.
+#[derive(Debug, Clone)]
+pub struct Stress {
+    backend: Backend,
+    strategy: LockStrategy,
+    processes: usize,
+    iterations: usize,
+    hold: Duration,
+}
+
+/// The outcome of a successful [`Stress::run`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+    /// The number of exclusive locks taken across all workers.
+    pub writes: usize,
+    /// The number of shared locks taken across all workers.
+    pub reads: usize,
+    /// The value of the counter incremented by every writer.
+    pub counter: u64,
+    /// The highest number of shared locks held at the same time.
+    pub max_readers: usize,
+}
+
+impl Stress {
+    /// Creates a stress test with 4 processes of 50 iterations each, locking
+    /// with [`LockStrategy::Flock`].
+    pub fn new(backend: Backend) -> Self {
+        Self {
+            backend,
+            strategy: LockStrategy::Flock,
+            processes: 4,
+            iterations: 50,
+            hold: Duration::from_millis(1),
+        }
+    }
+
+    /// Sets the number of worker processes.
+    pub fn processes(mut self, processes: usize) -> Self {
+        self.processes = processes;
+        self
+    }
+
+    /// Sets the number of locks each worker takes.
+    pub fn iterations(mut self, iterations: usize) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    /// Sets the mechanism used by the workers to lock the shared file.
+    pub fn strategy(mut self, strategy: LockStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Sets how long each lock is held for.
+    pub fn hold(mut self, hold: Duration) -> Self {
+        self.hold = hold;
+        self
+    }
+
+    /// Runs the stress test from within the test named `test_name`.
+    ///
+    /// The workers are spawned by running the current executable filtered to
+    /// `test_name`, so the test must be the one calling this function. Inside
+    /// of a worker, this function runs the worker and exits the process.
+    pub fn run(&self, test_name: &str) -> io::Result<Report> {
+        if let Ok(index) = env::var(WORKER_VAR) {
+            let code = match self.work(&index) {
+                Ok(()) => 0,
+                Err(error) => {
+                    // Picked up by the parent, which reports it.
+                    if let Some(dir) = env::var_os(DIR_VAR) {
+                        let path = PathBuf::from(dir).join(format!("{ERROR_FILE}-{index}"));
+                        let _ = fs::write(path, error.to_string());
+                    }
+                    1
+                }
+            };
+            std::process::exit(code);
+        }
+
+        let dir = TempDir::new()?;
+        File::create(dir.0.join(LOCK_FILE))?;
+        File::create(dir.0.join(JOURNAL_FILE))?;
+
+        let exe = env::current_exe()?;
+        let mut command = Command::new(&exe);
+        command
+            .args([test_name, "--exact", "--nocapture", "--test-threads=1"])
+            .env(DIR_VAR, &dir.0)
+            .env(ITERATIONS_VAR, self.iterations.to_string())
+            .env(HOLD_VAR, self.hold.as_micros().to_string())
+            .stdout(Stdio::null());
+        #[cfg(unix)]
+        if self.strategy == LockStrategy::Broker {
+            use crate::broker::{Broker, SOCKET_ENV};
+
+            let broker = Broker::bind(dir.0.join(BROKER_SOCKET))?;
+            command.env(SOCKET_ENV, broker.path());
+            // Keeps running for the rest of the test process, refusing
+            // connections once its socket is removed along with `dir`.
+            thread::spawn(move || broker.run());
+        }
+        let children = (0..self.processes)
+            .map(|index| command.env(WORKER_VAR, index.to_string()).spawn())
+            .collect::<io::Result<Vec<_>>>()?;
+        for (index, mut child) in children.into_iter().enumerate() {
+            let status = child.wait()?;
+            if !status.success() {
+                return Err(
+                    match fs::read_to_string(dir.0.join(format!("{ERROR_FILE}-{index}"))) {
+                        Ok(error) => {
+                            io::Error::other(format!("stress worker {index} failed: {error}"))
+                        }
+                        Err(_) => io::Error::other(format!("stress worker exited with {status}")),
+                    },
+                );
+            }
+        }
+
+        let mut report = replay(&fs::read_to_string(dir.0.join(JOURNAL_FILE))?)?;
+        report.counter = parse_counter(&fs::read_to_string(dir.0.join(LOCK_FILE))?)?;
+        if report.counter != report.writes as u64 {
+            return Err(invalid(format!(
+                "{} writes incremented the counter to {}",
+                report.writes, report.counter
+            )));
+        }
+        Ok(report)
+    }
+
+    fn work(&self, index: &str) -> io::Result<()> {
+        let dir = PathBuf::from(env::var_os(DIR_VAR).ok_or_else(|| invalid("missing directory"))?);
+        let iterations = parse_var(ITERATIONS_VAR)?;
+        let hold = Duration::from_micros(parse_var(HOLD_VAR)?);
+        let worker = Worker {
+            index: index.parse().map_err(invalid)?,
+            processes: self.processes,
+            iterations: iterations as usize,
+            hold,
+            strategy: self.strategy,
+            lock_path: dir.join(LOCK_FILE),
+            journal: OpenOptions::new()
+                .append(true)
+                .open(dir.join(JOURNAL_FILE))?,
+        };
+        match self.backend {
+            Backend::Blocking => worker.run_blocking(),
+            #[cfg(feature = "async")]
+            Backend::Async => tokio::runtime::Builder::new_current_thread()
+                .enable_time()
+                .build()?
+                .block_on(worker.run_async()),
+        }
+    }
+}
+
+struct Worker {
+    index: usize,
+    processes: usize,
+    iterations: usize,
+    hold: Duration,
+    strategy: LockStrategy,
+    lock_path: PathBuf,
+    journal: File,
+}
+
+impl Worker {
+    fn run_blocking(&self) -> io::Result<()> {
+        let mut random = Random(self.index as u64 + 1);
+        for _ in 0..self.iterations {
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&self.lock_path)?;
+            if random.next().is_multiple_of(3) {
+                let mut guard = file.lock_write_with(self.strategy)?;
+                self.log("+W")?;
+                let mut contents = String::new();
+                guard.read_to_string(&mut contents)?;
+                let counter = parse_counter(&contents)?;
+                thread::sleep(self.hold);
+                guard.seek(SeekFrom::Start(0))?;
+                guard.inner().set_len(0)?;
+                guard.write_all((counter + 1).to_string().as_bytes())?;
+                self.log("-W")?;
+            } else {
+                let mut guard = file.lock_read_with(self.strategy)?;
+                self.log("+R")?;
+                let mut contents = String::new();
+                guard.read_to_string(&mut contents)?;
+                parse_counter(&contents)?;
+                thread::sleep(self.hold);
+                self.log("-R")?;
+            }
+        }
+
+        self.barrier("DONE")?;
+        let _guard = match self.strategy {
+            LockStrategy::DotLock => None,
+            strategy => Some(File::open(&self.lock_path)?.lock_read_with(strategy)?),
+        };
+        self.barrier("RV")
+    }
+
+    #[cfg(feature = "async")]
+    async fn run_async(&self) -> io::Result<()> {
+        use crate::nonblocking::{LockRead, LockWrite};
+        use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+        let mut random = Random(self.index as u64 + 1);
+        for _ in 0..self.iterations {
+            let file = tokio::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&self.lock_path)
+                .await?;
+            if random.next().is_multiple_of(3) {
+                let mut guard = file.lock_write_with(self.strategy).await?;
+                self.log("+W")?;
+                let mut contents = String::new();
+                guard.read_to_string(&mut contents).await?;
+                let counter = parse_counter(&contents)?;
+                tokio::time::sleep(self.hold).await;
+                guard.seek(SeekFrom::Start(0)).await?;
+                guard.inner().set_len(0).await?;
+                guard
+                    .write_all((counter + 1).to_string().as_bytes())
+                    .await?;
+                guard.flush().await?;
+                self.log("-W")?;
+            } else {
+                let mut guard = file.lock_read_with(self.strategy).await?;
+                self.log("+R")?;
+                let mut contents = String::new();
+                guard.read_to_string(&mut contents).await?;
+                parse_counter(&contents)?;
+                tokio::time::sleep(self.hold).await;
+                self.log("-R")?;
+            }
+        }
+
+        self.barrier_async("DONE").await?;
+        let _guard = match self.strategy {
+            LockStrategy::DotLock => None,
+            strategy => Some(
+                tokio::fs::File::open(&self.lock_path)
+                    .await?
+                    .lock_read_with(strategy)
+                    .await?,
+            ),
+        };
+        self.barrier_async("RV").await
+    }
+
+    /// Appends a record to the journal in a single write, so that records of
+    /// different workers never interleave.
+    fn log(&self, operation: &str) -> io::Result<()> {
+        (&self.journal).write_all(format!("{operation} {}\n", self.index).as_bytes())
+    }
+
+    /// Logs `operation` and waits until every worker has logged it.
+    ///
+    /// When called while holding a shared lock, this only returns if all
+    /// workers can hold a shared lock at the same time.
+    fn barrier(&self, operation: &str) -> io::Result<()> {
+        self.log(operation)?;
+        let deadline = Instant::now() + BARRIER_TIMEOUT;
+        while !self.arrived(operation, deadline)? {
+            thread::sleep(BARRIER_POLL);
+        }
+        Ok(())
+    }
+
+    /// Like [`Worker::barrier`], without blocking the runtime.
+    #[cfg(feature = "async")]
+    async fn barrier_async(&self, operation: &str) -> io::Result<()> {
+        self.log(operation)?;
+        let deadline = Instant::now() + BARRIER_TIMEOUT;
+        while !self.arrived(operation, deadline)? {
+            tokio::time::sleep(BARRIER_POLL).await;
+        }
+        Ok(())
+    }
+
+    /// Returns whether every worker has logged `operation`, failing once
+    /// `deadline` has passed.
+    fn arrived(&self, operation: &str, deadline: Instant) -> io::Result<bool> {
+        let journal = fs::read_to_string(self.lock_path.with_file_name(JOURNAL_FILE))?;
+        let arrived = journal
+            .lines()
+            .filter(|line| line.split(' ').next() == Some(operation))
+            .count();
+        if arrived >= self.processes {
+            return Ok(true);
+        }
+        if Instant::now() > deadline {
+            return Err(io::Error::new(
+                ErrorKind::TimedOut,
+                format!(
+                    "only {arrived} of {} workers reached {operation}",
+                    self.processes
+                ),
+            ));
+        }
+        Ok(false)
+    }
+}
+
+/// Replays the journal, checking that writers always had exclusive access.
+fn replay(journal: &str) -> io::Result<Report> {
+    let mut report = Report {
+        writes: 0,
+        reads: 0,
+        counter: 0,
+        max_readers: 0,
+    };
+    let mut readers = 0usize;
+    let mut writer = false;
+    for (number, line) in journal.lines().enumerate() {
+        let operation = line.split(' ').next().unwrap_or_default();
+        match operation {
+            "+W" if !writer && readers == 0 => {
+                writer = true;
+                report.writes += 1;
+            }
+            "-W" if writer => writer = false,
+            "+R" | "RV" if !writer => {
+                readers += 1;
+                report.reads += 1;
+                report.max_readers = report.max_readers.max(readers);
+            }
+            "-R" if readers > 0 => readers -= 1,
+            "DONE" => {}
+            _ => {
+                return Err(invalid(format!(
+                    "journal line {} ({line:?}) violates mutual exclusion",
+                    number + 1
+                )))
+            }
+        }
+    }
+    Ok(report)
+}
+
+fn parse_counter(contents: &str) -> io::Result<u64> {
+    if contents.is_empty() {
+        return Ok(0);
+    }
+    contents
+        .parse()
+        .map_err(|_| invalid(format!("torn counter {contents:?}")))
+}
+
+fn parse_var(name: &str) -> io::Result<u64> {
+    env::var(name).map_err(invalid)?.parse().map_err(invalid)
+}
+
+fn invalid(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, error)
+}
+
+/// A xorshift generator, so that runs are reproducible without extra
+/// dependencies.
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+/// A uniquely named directory that is removed on drop.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> io::Result<Self> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let path = env::temp_dir().join(format!(
+            "async-fd-lock-stress-{}-{nanos}",
+            std::process::id()
+        ));
+        fs::create_dir(&path)?;
+        Ok(Self(path))
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,235 @@
+//! Read-modify-write transactions that roll back on failure.
+//!
+//! [`with_write_lock`] takes an exclusive lock and a snapshot of the file,
+//! then runs a closure on the guard. If the closure fails or panics, or its
+//! writes cannot be flushed, the file is restored from the snapshot before the
+//! lock is released, so the next holder of the lock never observes a partial
+//! update.
+//!
+//! The snapshot is a copy of the whole file in memory, taken through a
+//! duplicate of its handle, so the file must be open for reading.
+//!
+//! # Example
+//!
+//! ```
+//! use std::fs::OpenOptions;
+//! use std::io::{self, Seek, SeekFrom, Write};
+//! use async_fd_lock::transaction::with_write_lock;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let path = dir.path().join("foo.txt");
+//! std::fs::write(&path, "bongo cat")?;
+//! let file = OpenOptions::new().read(true).write(true).open(&path)?;
+//!
+//! let result = with_write_lock(&file, |guard| {
+//!     guard.seek(SeekFrom::Start(0))?;
+//!     guard.write_all(b"hello")?;
+//!     Err::<(), _>(io::Error::other("changed my mind"))
+//! });
+//! assert!(result.is_err());
+//! assert_eq!(std::fs::read_to_string(&path)?, "bongo cat");
+//! # io::Result::Ok(())
+//! ```
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::blocking::LockWrite;
+use crate::sys::{duplicate, AsOpenFile};
+use crate::RwLockWriteGuard;
+
+/// The state of a file before a transaction, restored on drop unless the
+/// transaction committed.
+struct Rollback {
+    file: File,
+    position: u64,
+    contents: Vec<u8>,
+    armed: bool,
+}
+
+/// Locks `file` exclusively and runs `transaction` on the guard, restoring
+/// the previous contents if it returns an error or panics, or if flushing the
+/// file afterwards fails.
+///
+/// If restoring the file fails after `transaction` returned an error, the
+/// error of the restore is returned instead.
+pub fn with_write_lock<T, R, E, F>(file: T, transaction: F) -> Result<R, E>
+where
+    T: AsOpenFile + Write,
+    E: From<io::Error>,
+    F: FnOnce(&mut RwLockWriteGuard<T>) -> Result<R, E>,
+{
+    let mut guard = file.lock_write().map_err(io::Error::from)?;
+    let mut rollback = Rollback::new(guard.inner())?;
+    match transaction(&mut guard) {
+        Ok(result) => {
+            // Buffered writes are part of the transaction.
+            if let Err(error) = guard.flush() {
+                rollback.restore()?;
+                guard.release()?;
+                return Err(error.into());
+            }
+            rollback.commit();
+            guard.release()?;
+            Ok(result)
+        }
+        Err(error) => {
+            let _ = guard.flush();
+            rollback.restore()?;
+            guard.release()?;
+            Err(error)
+        }
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "async")] {
+        use std::future::Future;
+        use std::pin::Pin;
+
+        use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+        /// The future returned by the closure passed to
+        /// [`with_write_lock_async`].
+        pub type TransactionFuture<'a, R, E> = Pin<Box<dyn Future<Output = Result<R, E>> + Send + 'a>>;
+
+        /// Like [`with_write_lock`], but for async files and closures.
+        ///
+        /// The file is also restored if the returned future is dropped before
+        /// it completes. The lock is then held by a task spawned on the
+        /// runtime until writes still in flight have completed and the file
+        /// was restored.
+        ///
+        /// ```
+        /// use tokio::io::AsyncWriteExt;
+        /// use async_fd_lock::transaction::with_write_lock_async;
+        ///
+        /// # tokio_test::block_on(async {
+        /// let dir = tempfile::tempdir().unwrap();
+        /// let path = dir.path().join("foo.txt");
+        /// let file = tokio::fs::OpenOptions::new()
+        ///     .read(true)
+        ///     .write(true)
+        ///     .create(true)
+        ///     .open(&path)
+        ///     .await?;
+        ///
+        /// with_write_lock_async(file, |guard| {
+        ///     Box::pin(async move { guard.write_all(b"bongo cat").await })
+        /// })
+        /// .await?;
+        /// assert_eq!(std::fs::read_to_string(&path)?, "bongo cat");
+        /// # std::io::Result::Ok(())
+        /// # }).unwrap();
+        /// ```
+        pub async fn with_write_lock_async<T, R, E, F>(file: T, transaction: F) -> Result<R, E>
+        where
+            T: AsOpenFile + AsyncWrite + Unpin + Send + Sync + 'static,
+            E: From<io::Error>,
+            F: for<'a> FnOnce(&'a mut RwLockWriteGuard<T>) -> TransactionFuture<'a, R, E>,
+        {
+            use crate::LockWrite;
+
+            let guard = file.lock_write().await.map_err(io::Error::from)?;
+            let handle = duplicate(guard.inner())?;
+            let rollback = spawn_blocking(move || Rollback::new(&handle)).await?;
+            let mut pending = Pending(Some((guard, rollback)));
+            let (guard, _) = pending.0.as_mut().expect("taken once completed");
+            let outcome = transaction(guard).await;
+            let (mut guard, mut rollback) = pending.0.take().expect("taken once completed");
+            match outcome {
+                Ok(result) => {
+                    if let Err(error) = guard.flush().await {
+                        spawn_blocking(move || rollback.restore()).await?;
+                        guard.release()?;
+                        return Err(error.into());
+                    }
+                    rollback.commit();
+                    guard.release()?;
+                    Ok(result)
+                }
+                Err(error) => {
+                    // Wait for writes that are still in flight.
+                    let _ = guard.flush().await;
+                    spawn_blocking(move || rollback.restore()).await?;
+                    guard.release()?;
+                    Err(error)
+                }
+            }
+        }
+
+        /// The lock and snapshot of a transaction that has not completed yet.
+        struct Pending<T>(Option<(RwLockWriteGuard<T>, Rollback)>)
+        where
+            T: AsOpenFile + AsyncWrite + Unpin + Send + Sync + 'static;
+
+        /// Restore the file if the transaction was canceled.
+        impl<T> Drop for Pending<T>
+        where
+            T: AsOpenFile + AsyncWrite + Unpin + Send + Sync + 'static,
+        {
+            fn drop(&mut self) {
+                let Some((mut guard, rollback)) = self.0.take() else {
+                    return;
+                };
+                let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+                    drop(rollback);
+                    drop(guard);
+                    return;
+                };
+                // Files like `tokio::fs::File` keep writing on a blocking task
+                // after the write returned, so the restore has to wait for them.
+                runtime.spawn(async move {
+                    let _ = guard.flush().await;
+                    spawn_blocking(move || drop(rollback)).await;
+                    drop(guard);
+                });
+            }
+        }
+
+        async fn spawn_blocking<R: Send + 'static>(f: impl FnOnce() -> R + Send + 'static) -> R {
+            tokio::task::spawn_blocking(f)
+                .await
+                .expect("the blocking task is not cancelable")
+        }
+    }
+}
+
+impl Rollback {
+    fn new<T: AsOpenFile>(file: &T) -> io::Result<Self> {
+        let mut file = duplicate(file)?;
+        let position = file.stream_position()?;
+        let mut contents = Vec::new();
+        file.seek(SeekFrom::Start(0))?;
+        file.read_to_end(&mut contents)?;
+        file.seek(SeekFrom::Start(position))?;
+        Ok(Self {
+            file,
+            position,
+            contents,
+            armed: true,
+        })
+    }
+
+    fn commit(&mut self) {
+        self.armed = false;
+    }
+
+    fn restore(&mut self) -> io::Result<()> {
+        self.armed = false;
+        self.file.set_len(self.contents.len() as u64)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&self.contents)?;
+        self.file.seek(SeekFrom::Start(self.position))?;
+        Ok(())
+    }
+}
+
+/// Restore the file if the transaction panicked or was canceled.
+impl Drop for Rollback {
+    fn drop(&mut self) {
+        if self.armed {
+            let _ = self.restore();
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::ffi::OsString;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use rustix::fd::OwnedFd;
+use rustix::fs::inotify::{self, CreateFlags, ReadFlags, WatchFlags};
+use tokio::io::unix::AsyncFd;
+
+/// The size of the header of an `inotify_event`.
+const HEADER_LEN: usize = 16;
+
+/// Watches a lock file, and the files next to it that come and go with its
+/// lock.
+#[derive(Debug)]
+pub(super) struct Inotify {
+    fd: AsyncFd<OwnedFd>,
+    file_watch: i32,
+    /// Names in the parent directory whose creation or removal concerns the
+    /// lock.
+    names: Vec<OsString>,
+}
+
+impl Inotify {
+    pub(super) fn new(path: &Path) -> io::Result<Self> {
+        let fd = inotify::init(CreateFlags::CLOEXEC | CreateFlags::NONBLOCK)?;
+        let file_watch = inotify::add_watch(
+            &fd,
+            path,
+            WatchFlags::CLOSE | WatchFlags::MODIFY | WatchFlags::ATTRIB,
+        )?;
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        inotify::add_watch(
+            &fd,
+            dir,
+            WatchFlags::CREATE | WatchFlags::DELETE | WatchFlags::MOVE | WatchFlags::ONLYDIR,
+        )?;
+        let name = path.file_name().unwrap_or_default();
+        // Holder sidecars and lock files of `DotLock`.
+        let names = [".holder", ".lock"]
+            .into_iter()
+            .map(|suffix| {
+                let mut sidecar = name.to_owned();
+                sidecar.push(suffix);
+                sidecar
+            })
+            .chain([name.to_owned()])
+            .collect();
+        // SAFETY: the descriptor is owned, so it stays open for as long as the
+        // `AsyncFd` does.
+        let fd = unsafe { AsyncFd::register(fd)? };
+        Ok(Self {
+            fd,
+            file_watch,
+            names,
+        })
+    }
+
+    /// Waits for an event that concerns the lock.
+    pub(super) async fn wait(&self) -> io::Result<()> {
+        loop {
+            let mut ready = self.fd.readable().await?;
+            match ready.try_io(|fd| self.read(fd.get_ref())) {
+                Ok(Ok(true)) => return Ok(()),
+                Ok(Ok(false)) | Err(_) => {}
+                Ok(Err(error)) => return Err(error),
+            }
+        }
+    }
+
+    /// Discards pending events.
+    pub(super) fn drain(&self) -> io::Result<()> {
+        loop {
+            match self.read(self.fd.get_ref()) {
+                Ok(_) => {}
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    /// Reads a batch of events, returning whether any of them concerns the
+    /// lock.
+    fn read(&self, fd: &OwnedFd) -> io::Result<bool> {
+        let mut buf = [0; 4096];
+        let len = rustix::io::read(fd, &mut buf)?;
+        let mut events = &buf[..len];
+        let mut relevant = false;
+        while events.len() >= HEADER_LEN {
+            let field = |index: usize| {
+                let bytes = events[index * 4..(index + 1) * 4].try_into();
+                u32::from_ne_bytes(bytes.expect("fields are 4 bytes"))
+            };
+            let (watch, mask, name_len) = (field(0) as i32, field(1), field(3) as usize);
+            let name = events
+                .get(HEADER_LEN..HEADER_LEN + name_len)
+                .unwrap_or_default();
+            // Names are padded with NUL bytes.
+            let name = name.split(|&byte| byte == 0).next().unwrap_or_default();
+            relevant |= watch == self.file_watch
+                || ReadFlags::from_bits_retain(mask).contains(ReadFlags::QUEUE_OVERFLOW)
+                || self
+                    .names
+                    .iter()
+                    .any(|candidate| candidate.as_bytes() == name);
+            events = events.get(HEADER_LEN + name_len..).unwrap_or_default();
+        }
+        Ok(relevant)
+    }
+}
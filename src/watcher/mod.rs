@@ -0,0 +1,196 @@
+//! Waiting for locks to change state without occupying a thread.
+//!
+//! A [`LockWatcher`] reports the state of the lock on a file, and waits for
+//! it to change. Changes are detected by probing the lock with non-blocking
+//! lock attempts, whenever the lock file, its [holder sidecar](crate::holder)
+//! or its [lock file](crate::dotlock) changes, and periodically otherwise. On
+//! Linux, changes are observed with inotify; elsewhere, only the periodic
+//! probes run. Releasing an advisory lock does not notify watchers by itself,
+//! so the probe interval bounds how late a release is noticed when nothing
+//! else changes.
+//!
+//! The `lock_*_watched` methods of [`nonblocking::LockRead`](crate::LockRead)
+//! and [`nonblocking::LockWrite`](crate::LockWrite) wait with a watcher rather
+//! than on a blocking task.
+//!
+//! # Example
+//!
+//! ```
+//! use async_fd_lock::blocking::LockWrite;
+//! use async_fd_lock::watcher::{LockState, LockWatcher};
+//!
+//! # tokio_test::block_on(async {
+//! let dir = tempfile::tempdir().unwrap();
+//! let path = dir.path().join("foo.txt");
+//! let guard = std::fs::File::create(&path)?.lock_write()?;
+//!
+//! let mut watcher = LockWatcher::new(&path)?;
+//! assert_eq!(watcher.probe()?, LockState::WriteLocked);
+//!
+//! std::thread::spawn(move || guard.release());
+//! watcher.available(true).await?;
+//! # std::io::Result::Ok(())
+//! # }).unwrap();
+//! ```
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod inotify;
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::blocking::{LockRead, LockWrite};
+use crate::sys::{path_of, AsOpenFile};
+use crate::LockStrategy;
+
+/// The state of a lock, as probed by a [`LockWatcher`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LockState {
+    /// Neither a shared nor an exclusive lock is held.
+    Free,
+    /// Shared locks are held.
+    ReadLocked,
+    /// An exclusive lock is held.
+    WriteLocked,
+}
+
+/// Watches the lock on a file.
+///
+/// Probing briefly takes the lock, so a concurrent non-blocking attempt by
+/// someone else may spuriously fail.
+#[derive(Debug)]
+pub struct LockWatcher {
+    path: PathBuf,
+    file: File,
+    strategy: LockStrategy,
+    probe_interval: Duration,
+    last: Option<LockState>,
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    inotify: Option<inotify::Inotify>,
+}
+
+impl LockWatcher {
+    /// Watches the lock on the file at `path`.
+    pub fn new(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .or_else(|error| match error.kind() {
+                ErrorKind::PermissionDenied => File::open(&path),
+                _ => Err(error),
+            })?;
+        Ok(Self {
+            path,
+            file,
+            strategy: LockStrategy::default(),
+            probe_interval: Duration::from_secs(1),
+            last: None,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            inotify: None,
+        })
+    }
+
+    /// Watches the lock on the file behind `file`.
+    pub(crate) fn for_file<T: AsOpenFile>(file: &T) -> io::Result<Self> {
+        Self::new(path_of(file)?)
+    }
+
+    /// Sets the mechanism used to probe the lock.
+    pub fn strategy(mut self, strategy: LockStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Sets how often the lock is probed when nothing else changes.
+    pub fn probe_interval(mut self, interval: Duration) -> Self {
+        self.probe_interval = interval;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the current state of the lock.
+    pub fn probe(&self) -> io::Result<LockState> {
+        match (&self.file).try_lock_write_with(self.strategy) {
+            Ok(guard) => {
+                guard.release()?;
+                return Ok(LockState::Free);
+            }
+            Err(error) if error.error.kind() == ErrorKind::WouldBlock => {}
+            Err(error) => return Err(error.error),
+        }
+        match (&self.file).try_lock_read_with(self.strategy) {
+            Ok(guard) => {
+                guard.release()?;
+                Ok(LockState::ReadLocked)
+            }
+            Err(error) if error.error.kind() == ErrorKind::WouldBlock => Ok(LockState::WriteLocked),
+            Err(error) => Err(error.error),
+        }
+    }
+
+    /// Waits for the state of the lock to differ from the one returned last,
+    /// returning the new state. The first call returns the current state.
+    pub async fn changed(&mut self) -> io::Result<LockState> {
+        let last = self.last;
+        let state = self.wait_until(|state| Some(state) != last).await?;
+        self.last = Some(state);
+        Ok(state)
+    }
+
+    /// Waits until an exclusive lock, or a shared lock if `exclusive` is
+    /// false, could be taken.
+    ///
+    /// Someone else may take the lock before the caller does.
+    pub async fn available(&mut self, exclusive: bool) -> io::Result<()> {
+        self.wait_until(|state| match state {
+            LockState::Free => true,
+            LockState::ReadLocked => !exclusive,
+            LockState::WriteLocked => false,
+        })
+        .await
+        .map(drop)
+    }
+
+    async fn wait_until(&mut self, done: impl Fn(LockState) -> bool) -> io::Result<LockState> {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        if self.inotify.is_none() {
+            // Without inotify, the periodic probes still notice changes.
+            self.inotify = inotify::Inotify::new(&self.path).ok();
+        }
+        loop {
+            let state = self.probe()?;
+            if done(state) {
+                return Ok(state);
+            }
+            self.wait().await?;
+        }
+    }
+
+    /// Waits for a change that may concern the lock, or for the probe
+    /// interval to pass.
+    async fn wait(&self) -> io::Result<()> {
+        cfg_if::cfg_if! {
+            if #[cfg(any(target_os = "linux", target_os = "android"))] {
+                if let Some(inotify) = &self.inotify {
+                    // Discard events caused by the last probe, such as the lock
+                    // files of `DotLock`. Anything else that happened since is
+                    // noticed by the next periodic probe at the latest.
+                    inotify.drain()?;
+                    return match tokio::time::timeout(self.probe_interval, inotify.wait()).await {
+                        Ok(result) => result,
+                        Err(_elapsed) => Ok(()),
+                    };
+                }
+            }
+        }
+        tokio::time::sleep(self.probe_interval).await;
+        Ok(())
+    }
+}
@@ -1,12 +1,17 @@
 use std::{
     io::{self, BufRead, Read, Seek, Write},
+    mem,
+    path::PathBuf,
     pin::Pin,
 };
 
 use cfg_if::cfg_if;
 use pin_project::{pin_project, pinned_drop};
 
+use crate::holder::{default_sidecar, Holder, Sidecar};
+use crate::strategy::Held;
 use crate::sys::{AsOpenFile, AsOpenFileExt, RwLockGuard};
+use crate::{LockError, LockStrategy, MappedRwLockWriteGuard, OwnedLock};
 
 /// An exclusive lock on a file.
 ///
@@ -19,12 +24,25 @@ use crate::sys::{AsOpenFile, AsOpenFileExt, RwLockGuard};
 pub struct RwLockWriteGuard<T: AsOpenFile> {
     #[pin]
     file: Option<T>,
+    held: Held,
 }
 
 impl<T: AsOpenFile> RwLockWriteGuard<T> {
     pub(crate) fn new<F: AsOpenFile>(file: T, guard: RwLockGuard<F>) -> Self {
-        guard.defuse();
-        Self { file: Some(file) }
+        Self::from_held(file, guard.defuse())
+    }
+
+    pub(crate) fn from_held(file: T, held: Held) -> Self {
+        held.track(&file);
+        Self {
+            file: Some(file),
+            held,
+        }
+    }
+
+    /// Returns the mechanism used to hold the lock.
+    pub fn strategy(&self) -> LockStrategy {
+        self.held.strategy()
     }
 
     pub fn inner(&self) -> &T {
@@ -46,10 +64,78 @@ impl<T: AsOpenFile> RwLockWriteGuard<T> {
             .expect("file only removed during release")
     }
 
+    /// Splits the guard into the inner file and the lock, without releasing
+    /// the lock.
+    ///
+    /// Fails if the handle of the file cannot be duplicated, returning the
+    /// guard back.
+    pub fn into_parts(mut self) -> Result<(T, OwnedLock), LockError<Self>> {
+        let handle = match self.inner().borrow_open_file().try_clone_to_owned() {
+            Ok(handle) => handle,
+            Err(error) => return Err(LockError::new(self, error)),
+        };
+        let file = self.file.take().expect("file only removed during release");
+        // The file is gone, so the placeholder is never released.
+        let held = mem::replace(&mut self.held, Held::os(LockStrategy::default()));
+        Ok((file, OwnedLock::new(handle, held)))
+    }
+
+    /// Reassembles a guard split by [`RwLockWriteGuard::into_parts`].
+    ///
+    /// `file` must refer to the same open file as the one the lock was
+    /// acquired through, otherwise the lock will not be released correctly.
+    pub fn from_parts(file: T, lock: OwnedLock) -> Self {
+        Self::from_held(file, lock.into_held())
+    }
+
+    /// Wraps the inner file, for example in a [`BufReader`](std::io::BufReader),
+    /// while keeping the lock held.
+    pub fn map<U>(
+        self,
+        map: impl FnOnce(T) -> U,
+    ) -> Result<MappedRwLockWriteGuard<U>, LockError<Self>> {
+        let (file, lock) = self.into_parts()?;
+        Ok(MappedRwLockWriteGuard::from_parts(map(file), lock))
+    }
+
+    /// Like [`RwLockWriteGuard::map`], but with a fallible wrapper. The lock is released
+    /// if `map` fails.
+    pub fn try_map<U, E>(
+        self,
+        map: impl FnOnce(T) -> Result<U, E>,
+    ) -> Result<MappedRwLockWriteGuard<U>, E>
+    where
+        E: From<io::Error>,
+    {
+        let (file, lock) = self.into_parts().map_err(io::Error::from)?;
+        Ok(MappedRwLockWriteGuard::from_parts(map(file)?, lock))
+    }
+
+    /// Publishes a [`Holder`] record describing this process to the default
+    /// sidecar path of the file, see [`holder`](crate::holder).
+    ///
+    /// The record is removed right before the lock is released. Only
+    /// supported on Linux, where the path of an open file can be recovered.
+    pub fn publish_holder(&mut self, purpose: impl Into<String>) -> io::Result<()> {
+        let sidecar = default_sidecar(self.inner())?;
+        self.publish_holder_at(sidecar, purpose)
+    }
+
+    /// Like [`RwLockWriteGuard::publish_holder`], but writes the record to
+    /// `sidecar`.
+    pub fn publish_holder_at(
+        &mut self,
+        sidecar: impl Into<PathBuf>,
+        purpose: impl Into<String>,
+    ) -> io::Result<()> {
+        let sidecar = Sidecar::write(sidecar.into(), &Holder::current(purpose))?;
+        self.held.set_sidecar(sidecar)
+    }
+
     /// Releases the lock, returning the inner file.
     pub fn release(mut self) -> io::Result<T> {
         let file = self.file.take().expect("file only removed during release");
-        file.release_lock_blocking()?;
+        self.held.release(&file)?;
         Ok(file)
     }
 }
@@ -230,8 +316,9 @@ cfg_if! {
 impl<T: AsOpenFile> PinnedDrop for RwLockWriteGuard<T> {
     #[inline]
     fn drop(self: Pin<&mut Self>) {
-        if let Some(file) = self.project().file.as_pin_mut() {
-            let _ = file.release_lock_blocking();
+        let this = self.project();
+        if let Some(file) = this.file.as_pin_mut() {
+            let _ = this.held.release(&*file);
         }
     }
 }
@@ -0,0 +1,139 @@
+use async_fd_lock::append_log::AppendLog;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::thread;
+use tempfile::tempdir;
+
+#[test]
+fn frames_round_trip() {
+    let dir = tempdir().unwrap();
+    let log = AppendLog::open(dir.path().join("log")).unwrap();
+
+    assert_eq!(log.append(b"").unwrap(), 0);
+    assert_eq!(log.append_batch(["bongo", "cat"]).unwrap(), 8);
+
+    let mut reader = log.reader().unwrap();
+    assert_eq!(reader.next().unwrap().unwrap(), b"");
+    assert_eq!(reader.offset(), 8);
+    assert_eq!(reader.next().unwrap().unwrap(), b"bongo");
+    assert_eq!(reader.next().unwrap().unwrap(), b"cat");
+    assert!(reader.next().is_none());
+    assert_eq!(reader.offset(), 32);
+}
+
+#[test]
+fn reader_detects_damage() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("log");
+    let log = AppendLog::open(&path).unwrap();
+    log.append_batch(["bongo", "cat"]).unwrap();
+
+    // A torn frame at the end.
+    let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(&[3, 0, 0, 0, 0, 0]).unwrap();
+    let records = log.reader().unwrap().collect::<Vec<_>>();
+    assert_eq!(records.len(), 3);
+    assert_eq!(
+        records[2].as_ref().unwrap_err().kind(),
+        ErrorKind::UnexpectedEof
+    );
+
+    // A flipped bit in the first record.
+    let mut bytes = fs::read(&path).unwrap();
+    bytes[8] ^= 1;
+    fs::write(&path, bytes).unwrap();
+    let records = log.reader().unwrap().collect::<Vec<_>>();
+    assert_eq!(records.len(), 1);
+    assert_eq!(
+        records[0].as_ref().unwrap_err().kind(),
+        ErrorKind::InvalidData
+    );
+}
+
+#[test]
+fn append_cuts_off_torn_frame() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("log");
+    let log = AppendLog::open(&path).unwrap();
+    log.append_batch(["bongo", "cat"]).unwrap();
+
+    // Cut the last frame off in the middle of its record.
+    let file = OpenOptions::new().write(true).open(&path).unwrap();
+    file.set_len(20).unwrap();
+    assert_eq!(log.append(b"drums").unwrap(), 13);
+
+    // A frame whose record was never written, from another process.
+    let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(&[3, 0, 0, 0, 1, 2, 3, 4, 0, 0, 0]).unwrap();
+    assert_eq!(AppendLog::open(&path).unwrap().append(b"cat").unwrap(), 26);
+
+    let records = log
+        .reader()
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(records, [&b"bongo"[..], b"drums", b"cat"]);
+
+    // Damage before the end is left for readers to report.
+    let mut bytes = fs::read(&path).unwrap();
+    bytes[8] ^= 1;
+    fs::write(&path, bytes).unwrap();
+    let err = AppendLog::open(&path).unwrap().append(b"").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn concurrent_batches_stay_contiguous() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("log");
+
+    thread::scope(|scope| {
+        for writer in 0..8u8 {
+            let log = AppendLog::open(&path).unwrap();
+            scope.spawn(move || {
+                for batch in 0..50u8 {
+                    let records = (0..4u8).map(|i| [writer, batch, i].repeat(100));
+                    log.append_batch(records).unwrap();
+                }
+            });
+        }
+    });
+
+    let records = AppendLog::open(&path)
+        .unwrap()
+        .reader()
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(records.len(), 8 * 50 * 4);
+    for batch in records.chunks(4) {
+        for (i, record) in batch.iter().enumerate() {
+            assert_eq!(record[..3], [batch[0][0], batch[0][1], i as u8]);
+        }
+    }
+}
+
+#[test]
+fn shared_log_appends_are_not_cut_off() {
+    let dir = tempdir().unwrap();
+    let log = AppendLog::open(dir.path().join("log")).unwrap();
+
+    thread::scope(|scope| {
+        for writer in 0..8u8 {
+            let log = &log;
+            scope.spawn(move || {
+                for record in 0..300u16 {
+                    let [high, low] = record.to_le_bytes();
+                    log.append(&[writer, high, low].repeat(50)).unwrap();
+                }
+            });
+        }
+    });
+
+    let records = log
+        .reader()
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(records.len(), 8 * 300);
+}
@@ -0,0 +1,128 @@
+#![cfg(unix)]
+
+use async_fd_lock::blocking::LockWrite;
+use async_fd_lock::broker::{Broker, Client};
+use async_fd_lock::LockStrategy;
+use std::fs::File;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::tempdir;
+
+fn spawn_broker(dir: &Path) -> Client {
+    let broker = Broker::bind(dir.join("broker.sock")).unwrap();
+    let client = Client::new(broker.path());
+    thread::spawn(move || broker.run());
+    client
+}
+
+#[test]
+fn readers_share_writers_exclude() {
+    let dir = tempdir().unwrap();
+    let client = spawn_broker(dir.path()).name("shared");
+    let path = dir.path().join("foo.txt");
+    File::create(&path).unwrap();
+
+    let first = client.lock_read(File::open(&path).unwrap()).unwrap();
+    let second = client.try_lock_read(File::open(&path).unwrap()).unwrap();
+    assert_eq!(first.strategy(), LockStrategy::Broker);
+    let err = client
+        .try_lock_write(File::create(&path).unwrap())
+        .unwrap_err();
+    assert_eq!(err.error.kind(), ErrorKind::WouldBlock);
+    first.release().unwrap();
+    drop(second);
+
+    let guard = client.try_lock_write(File::create(&path).unwrap()).unwrap();
+    let err = client
+        .try_lock_read(File::open(&path).unwrap())
+        .unwrap_err();
+    assert_eq!(err.error.kind(), ErrorKind::WouldBlock);
+
+    // Other names are independent.
+    let other = client.clone().name("other");
+    let _other = other.try_lock_write(File::create(&path).unwrap()).unwrap();
+    drop(guard);
+}
+
+#[test]
+fn waiter_is_granted_on_release() {
+    let dir = tempdir().unwrap();
+    let client = spawn_broker(dir.path()).name("queue");
+    let path = dir.path().join("foo.txt");
+
+    let guard = client.lock_write(File::create(&path).unwrap()).unwrap();
+    let (send, recv) = mpsc::channel();
+    let waiter = {
+        let (client, path) = (client.clone(), path.clone());
+        thread::spawn(move || {
+            let guard = client.lock_write(File::create(path).unwrap()).unwrap();
+            send.send(()).unwrap();
+            guard.release().unwrap();
+        })
+    };
+    assert!(recv.recv_timeout(Duration::from_millis(200)).is_err());
+    guard.release().unwrap();
+    recv.recv_timeout(Duration::from_secs(5)).unwrap();
+    waiter.join().unwrap();
+}
+
+#[test]
+fn lease_expires_on_disconnect() {
+    let dir = tempdir().unwrap();
+    let client = spawn_broker(dir.path()).name("crashy");
+    let path = dir.path().join("foo.txt");
+
+    let stream = UnixStream::connect(client.socket()).unwrap();
+    (&stream).write_all(b"lock write wait crashy\n").unwrap();
+    let mut response = String::new();
+    BufReader::new(&stream).read_line(&mut response).unwrap();
+    assert_eq!(response, "granted\n");
+    let err = client
+        .try_lock_write(File::create(&path).unwrap())
+        .unwrap_err();
+    assert_eq!(err.error.kind(), ErrorKind::WouldBlock);
+
+    drop(stream);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        match client.try_lock_write(File::create(&path).unwrap()) {
+            Ok(guard) => {
+                guard.release().unwrap();
+                break;
+            }
+            Err(err) if err.error.kind() == ErrorKind::WouldBlock => {
+                assert!(Instant::now() < deadline, "lease did not expire");
+                thread::sleep(Duration::from_millis(10));
+            }
+            Err(err) => panic!("{}", err.error),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn broker_strategy() {
+    let dir = tempdir().unwrap();
+    let client = spawn_broker(dir.path());
+    std::env::set_var(async_fd_lock::broker::SOCKET_ENV, client.socket());
+    let path = dir.path().join("foo.txt");
+
+    let guard = File::create(&path)
+        .unwrap()
+        .lock_write_with(LockStrategy::Broker)
+        .unwrap();
+    assert_eq!(guard.strategy(), LockStrategy::Broker);
+
+    // The lock is named after the path, so the broker sees the conflict even
+    // though the kernel does not.
+    let err = client
+        .try_lock_write(File::create(&path).unwrap())
+        .unwrap_err();
+    assert_eq!(err.error.kind(), ErrorKind::WouldBlock);
+    let _kernel = File::create(&path).unwrap().try_lock_write().unwrap();
+    guard.release().unwrap();
+}
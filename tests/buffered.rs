@@ -0,0 +1,105 @@
+use async_fd_lock::blocking::{LockRead, LockWrite};
+use async_fd_lock::AsOpenFile;
+use std::fs::{self, File};
+use std::io::{BufRead, ErrorKind, Write};
+use tempfile::tempdir;
+
+fn assert_open_file<T: AsOpenFile>(_: &T) {}
+
+#[test]
+fn blocking_writer_flushes_on_drop() {
+    use async_fd_lock::blocking::LockedBufWriter;
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+
+    let mut writer = LockedBufWriter::new(File::create(&path).unwrap().lock_write().unwrap());
+    assert_open_file(&writer);
+    writer.write_all(b"bongo cat").unwrap();
+    assert_eq!(writer.buffer(), b"bongo cat");
+    assert_eq!(fs::read(&path).unwrap(), b"");
+
+    let err = File::open(&path).unwrap().try_lock_read().unwrap_err();
+    assert_eq!(err.error.kind(), ErrorKind::WouldBlock);
+
+    drop(writer);
+    let _guard = File::open(&path).unwrap().try_lock_read().unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"bongo cat");
+}
+
+#[test]
+fn blocking_reader() {
+    use async_fd_lock::blocking::LockedBufReader;
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+    fs::write(&path, "first\nsecond\n").unwrap();
+
+    let mut reader = LockedBufReader::new(File::open(&path).unwrap().lock_read().unwrap());
+    assert_open_file(&reader);
+    let lines = (&mut reader)
+        .lines()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(lines, ["first", "second"]);
+
+    let err = File::open(&path).unwrap().try_lock_write().unwrap_err();
+    assert_eq!(err.error.kind(), ErrorKind::WouldBlock);
+
+    reader.release().unwrap();
+    let _guard = File::open(&path).unwrap().try_lock_write().unwrap();
+}
+
+#[tokio::test]
+async fn async_writer_flushes_on_release() {
+    use async_fd_lock::{LockWrite, LockedBufWriter};
+    use tokio::io::AsyncWriteExt;
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+
+    let file = tokio::fs::File::create(&path).await.unwrap();
+    let mut writer = LockedBufWriter::new(file.lock_write().await.unwrap());
+    writer.write_all(b"bongo cat").await.unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"");
+
+    writer.release().await.unwrap();
+    let _guard = File::open(&path).unwrap().try_lock_read().unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"bongo cat");
+}
+
+#[tokio::test]
+async fn async_writer_drop_flushes_before_unlocking() {
+    use async_fd_lock::{LockWrite, LockedBufWriter};
+    use tokio::io::AsyncWriteExt;
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+
+    let file = tokio::fs::File::create(&path).await.unwrap();
+    let mut writer = LockedBufWriter::new(file.lock_write().await.unwrap());
+    writer.write_all(b"bongo cat").await.unwrap();
+    assert_eq!(writer.buffer(), b"bongo cat");
+    drop(writer);
+
+    let _guard = File::open(&path).unwrap().try_lock_read().unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"bongo cat");
+}
+
+#[tokio::test]
+async fn async_reader() {
+    use async_fd_lock::{LockRead, LockedBufReader};
+    use tokio::io::AsyncBufReadExt;
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+    fs::write(&path, "first\nsecond\n").unwrap();
+
+    let file = tokio::fs::File::open(&path).await.unwrap();
+    let mut reader = LockedBufReader::new(file.lock_read().await.unwrap());
+    assert_open_file(&reader);
+    let mut line = String::new();
+    reader.read_line(&mut line).await.unwrap();
+    assert_eq!(line, "first\n");
+    assert_eq!(reader.buffer(), b"second\n");
+}
@@ -0,0 +1,269 @@
+use async_fd_lock::blocking::{LockRead, LockWrite};
+use std::fs::File;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use tempfile::tempdir;
+
+fn cli() -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_async-fd-lock"));
+    command.stdout(Stdio::null()).stderr(Stdio::null());
+    command
+}
+
+/// A command that succeeds on every platform.
+fn succeed() -> [&'static str; 2] {
+    [env!("CARGO_BIN_EXE_async-fd-lock"), "--version"]
+}
+
+#[test]
+fn runs_command_while_holding_lock() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+
+    let status = cli().arg(&path).args(succeed()).status().unwrap();
+    assert!(status.success());
+    assert!(path.exists());
+
+    // The lock is held while the command runs.
+    let status = cli()
+        .args(["--nonblock", "--conflict-exit-code", "42"])
+        .arg(&path)
+        .arg(env!("CARGO_BIN_EXE_async-fd-lock"))
+        .args(["--nonblock", "-E", "43"])
+        .arg(&path)
+        .args(succeed())
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(43));
+}
+
+#[test]
+fn conflicts() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+    let _guard = File::create(&path).unwrap().lock_read().unwrap();
+
+    let status = cli()
+        .args(["--shared", "--nonblock"])
+        .arg(&path)
+        .args(succeed())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let status = cli()
+        .args(["--exclusive", "--nonblock", "--conflict-exit-code", "42"])
+        .arg(&path)
+        .args(succeed())
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(42));
+
+    let start = Instant::now();
+    let status = cli()
+        .args(["--timeout", "0.2", "-E", "42"])
+        .arg(&path)
+        .args(succeed())
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(42));
+    assert!(start.elapsed() >= Duration::from_millis(200));
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn agrees_with_library_strategy() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+    let _guard = File::create(&path)
+        .unwrap()
+        .lock_write_with(async_fd_lock::LockStrategy::Fcntl)
+        .unwrap();
+
+    let status = cli()
+        .args(["--strategy", "fcntl", "--nonblock", "-E", "42"])
+        .arg(&path)
+        .args(succeed())
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(42));
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn exclusive_record_lock_needs_write_access() {
+    let dir = tempdir().unwrap();
+
+    let output = cli()
+        .args(["--strategy", "fcntl"])
+        .arg(dir.path())
+        .args(succeed())
+        .stderr(Stdio::piped())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("try --shared"));
+
+    for args in [
+        ["--strategy", "fcntl", "--shared"],
+        ["--strategy", "flock", "-x"],
+    ] {
+        let status = cli().args(args).arg(dir.path()).args(succeed()).status();
+        assert!(status.unwrap().success());
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn forwards_exit_code() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+
+    let status = cli()
+        .arg(&path)
+        .args(["sh", "-c", "exit 7"])
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(7));
+
+    let status = cli()
+        .arg(&path)
+        .arg("/nonexistent/command")
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(127));
+}
+
+#[test]
+fn status() {
+    let dir = tempdir().unwrap();
+    let free = dir.path().join("free");
+    let read = dir.path().join("read");
+    let write = dir.path().join("write");
+    File::create(&free).unwrap();
+    let _read_guard = File::create(&read).unwrap().lock_read().unwrap();
+    let _write_guard = File::create(&write).unwrap().lock_write().unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_async-fd-lock"))
+        .args(["status", "--json"])
+        .args([&free, &read, &write, &dir.path().join("missing")])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let reports: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let states = reports
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|report| report["state"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(states, ["free", "read-locked", "write-locked", "missing"]);
+
+    if cfg!(target_os = "linux") {
+        let holders = &reports[2]["holders"];
+        assert_eq!(holders[0]["kind"], "FLOCK");
+        assert_eq!(holders[0]["mode"], "WRITE");
+        assert_eq!(holders[0]["pid"], std::process::id());
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn status_with_record_locks() {
+    let dir = tempdir().unwrap();
+    let free = dir.path().join("free");
+    File::create(&free).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_async-fd-lock"))
+        .args(["status", "--json", "--strategy", "fcntl"])
+        .args([&free, dir.path()])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let reports: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(reports[0]["state"], "free");
+    // Directories can only be probed with a shared lock.
+    assert_eq!(reports[1]["state"], "not-write-locked");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn broker_subcommand() {
+    use async_fd_lock::broker::{Client, SOCKET_ENV};
+
+    let dir = tempdir().unwrap();
+    let socket = dir.path().join("broker.sock");
+    let path = dir.path().join("lockfile");
+    let mut broker = cli()
+        .args(["broker", "--socket"])
+        .arg(&socket)
+        .spawn()
+        .unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !socket.exists() {
+        assert!(Instant::now() < deadline, "broker did not start");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    let guard = Client::new(&socket)
+        .lock_write(File::create(&path).unwrap())
+        .unwrap();
+    let status = cli()
+        .env(SOCKET_ENV, &socket)
+        .args(["--strategy", "broker", "--nonblock", "-E", "42"])
+        .arg(&path)
+        .args(succeed())
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(42));
+    guard.release().unwrap();
+
+    let status = cli()
+        .env(SOCKET_ENV, &socket)
+        .args(["--strategy", "broker", "--nonblock"])
+        .arg(&path)
+        .args(succeed())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    broker.kill().unwrap();
+    broker.wait().unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn broker_default_socket_is_private() {
+    use async_fd_lock::broker::SOCKET_ENV;
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempdir().unwrap();
+    let private = dir
+        .path()
+        .join(format!("async-fd-lock-{}", unsafe { libc::getuid() }));
+    let broker = || {
+        let mut command = cli();
+        command
+            .arg("broker")
+            .env("TMPDIR", dir.path())
+            .env_remove("XDG_RUNTIME_DIR")
+            .env_remove(SOCKET_ENV);
+        command
+    };
+
+    let mut child = broker().spawn().unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !private.join("broker.sock").exists() {
+        assert!(Instant::now() < deadline, "broker did not start");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let mode = std::fs::metadata(&private).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o700);
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    // A directory others can write to is refused.
+    std::fs::set_permissions(&private, std::fs::Permissions::from_mode(0o777)).unwrap();
+    let output = broker().stderr(Stdio::piped()).output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not private"));
+}
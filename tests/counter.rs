@@ -0,0 +1,113 @@
+use async_fd_lock::counter::FileCounter;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::ErrorKind;
+use std::thread;
+use tempfile::tempdir;
+
+#[test]
+fn falls_back_to_previous_slot() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("counter");
+    let counter = FileCounter::open(&path).unwrap();
+    assert_eq!(counter.get().unwrap(), 0);
+    assert_eq!(counter.increment().unwrap(), 1);
+    assert_eq!(counter.increment().unwrap(), 2);
+
+    // Tear the slot that holds 2.
+    let mut bytes = fs::read(&path).unwrap();
+    bytes.truncate(18);
+    fs::write(&path, &bytes).unwrap();
+    assert_eq!(counter.get().unwrap(), 1);
+    assert_eq!(counter.increment().unwrap(), 2);
+
+    // Corrupt both slots.
+    fs::write(&path, [0xff; 24]).unwrap();
+    let err = counter.increment().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn concurrent_increments_are_unique() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("counter");
+
+    let ids = thread::scope(|scope| {
+        let threads = (0..8)
+            .map(|_| {
+                let counter = FileCounter::open(&path).unwrap();
+                scope.spawn(move || {
+                    (0..50)
+                        .map(|_| counter.increment().unwrap())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        threads
+            .into_iter()
+            .flat_map(|thread| thread.join().unwrap())
+            .collect::<BTreeSet<_>>()
+    });
+    assert_eq!(ids, (1..=400).collect());
+}
+
+#[test]
+fn shared_counter_increments_are_unique() {
+    let dir = tempdir().unwrap();
+    let counter = FileCounter::open(dir.path().join("counter")).unwrap();
+
+    let ids = thread::scope(|scope| {
+        let threads = (0..8)
+            .map(|_| {
+                scope.spawn(|| {
+                    (0..50)
+                        .map(|_| counter.increment().unwrap())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        threads
+            .into_iter()
+            .flat_map(|thread| thread.join().unwrap())
+            .collect::<BTreeSet<_>>()
+    });
+    assert_eq!(ids, (1..=400).collect());
+}
+
+#[tokio::test]
+async fn shared_counter_async_increments_are_unique() {
+    let dir = tempdir().unwrap();
+    let counter = std::sync::Arc::new(FileCounter::open(dir.path().join("counter")).unwrap());
+
+    let tasks = (0..8)
+        .map(|_| {
+            let counter = counter.clone();
+            tokio::spawn(async move {
+                let mut ids = Vec::new();
+                for _ in 0..50 {
+                    ids.push(counter.increment_async().await.unwrap());
+                }
+                ids
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut ids = BTreeSet::new();
+    for task in tasks {
+        ids.extend(task.await.unwrap());
+    }
+    assert_eq!(ids, (1..=400).collect());
+}
+
+#[tokio::test]
+async fn async_reserve() {
+    let dir = tempdir().unwrap();
+    let counter = FileCounter::open(dir.path().join("counter")).unwrap();
+
+    assert_eq!(counter.reserve_async(5).await.unwrap(), 1..6);
+    assert_eq!(counter.increment_async().await.unwrap(), 6);
+    assert_eq!(counter.get_async().await.unwrap(), 6);
+
+    counter.reserve_async(u64::MAX - 7).await.unwrap();
+    let err = counter.increment_async().await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
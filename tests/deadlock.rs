@@ -0,0 +1,149 @@
+use async_fd_lock::blocking::{LockRead, LockWrite};
+use async_fd_lock::deadlock::{self, DeadlockReport, Detection};
+use async_fd_lock::reentrant::Owner;
+use std::fs::File;
+use std::io::ErrorKind;
+use std::sync::Barrier;
+use std::thread;
+use tempfile::tempdir;
+
+// Every test enables the same mode, since detection is global to the process.
+
+fn report(error: &std::io::Error) -> &DeadlockReport {
+    error.get_ref().unwrap().downcast_ref().unwrap()
+}
+
+#[test]
+fn opposite_order_is_detected() {
+    deadlock::set_detection(Detection::Error);
+    let dir = tempdir().unwrap();
+    let first = dir.path().join("first");
+    let second = dir.path().join("second");
+    let barrier = Barrier::new(2);
+
+    let lock_both = |outer: &std::path::Path, inner: &std::path::Path| {
+        let _outer = File::create(outer).unwrap().lock_write().unwrap();
+        barrier.wait();
+        // Dropping the outer lock on failure lets the other thread proceed.
+        File::create(inner)
+            .unwrap()
+            .lock_write()
+            .map(drop)
+            .map_err(|err| err.error)
+    };
+    let (left, right) = thread::scope(|scope| {
+        let left = scope.spawn(|| lock_both(&first, &second));
+        let right = scope.spawn(|| lock_both(&second, &first));
+        (left.join().unwrap(), right.join().unwrap())
+    });
+
+    let err = match (left, right) {
+        (Ok(()), Err(err)) | (Err(err), Ok(())) => err,
+        results => panic!("expected exactly one deadlock, got {results:?}"),
+    };
+    assert_eq!(err.kind(), ErrorKind::Deadlock);
+    let cycle = report(&err).cycle();
+    assert_eq!(cycle.len(), 2);
+    assert_ne!(cycle[0].owner, cycle[1].owner);
+    assert!(cycle.iter().all(|wait| wait.write));
+}
+
+#[test]
+fn waiting_for_own_lock_is_detected() {
+    deadlock::set_detection(Detection::Error);
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("foo.txt");
+
+    let guard = File::create(&path).unwrap().lock_read().unwrap();
+    let shared = File::open(&path).unwrap().lock_read().unwrap();
+    let err = File::create(&path).unwrap().lock_write().unwrap_err();
+    assert_eq!(err.error.kind(), ErrorKind::Deadlock);
+    let cycle = report(&err.error).cycle();
+    assert_eq!(cycle.len(), 1);
+    assert_eq!(cycle[0].owner, Owner::current());
+    assert!(report(&err.error).to_string().contains("foo.txt"));
+
+    // Non-blocking attempts cannot deadlock.
+    let err = File::create(&path).unwrap().try_lock_write().unwrap_err();
+    assert_eq!(err.error.kind(), ErrorKind::WouldBlock);
+    drop(shared);
+    drop(guard);
+    let _guard = File::create(&path).unwrap().lock_write().unwrap();
+}
+
+#[test]
+fn other_owners_are_waited_for() {
+    deadlock::set_detection(Detection::Error);
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("foo.txt");
+
+    let guard = File::create(&path).unwrap().lock_write().unwrap();
+    thread::scope(|scope| {
+        let waiter = scope.spawn(|| File::create(&path).unwrap().lock_write().map(drop));
+        thread::sleep(std::time::Duration::from_millis(50));
+        drop(guard);
+        assert!(waiter.join().unwrap().is_ok());
+    });
+}
+
+#[tokio::test]
+async fn scopes_are_owners() {
+    use async_fd_lock::{LockRead as _, LockWrite as _};
+
+    deadlock::set_detection(Detection::Error);
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("foo.txt");
+
+    async_fd_lock::reentrant::scope(async {
+        let _guard = tokio::fs::File::create(&path)
+            .await
+            .unwrap()
+            .lock_read()
+            .await
+            .unwrap();
+        let err = tokio::fs::File::create(&path)
+            .await
+            .unwrap()
+            .lock_write()
+            .await
+            .unwrap_err();
+        assert_eq!(err.error.kind(), ErrorKind::Deadlock);
+        assert!(matches!(
+            report(&err.error).cycle()[0].owner,
+            Owner::Scope(_)
+        ));
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn futures_outside_of_scopes_are_not_owners() {
+    use async_fd_lock::{LockWrite as _, RwLockWriteGuard};
+
+    deadlock::set_detection(Detection::Error);
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("foo.txt");
+
+    // Both futures run on the thread blocking on the test, and the waiting
+    // one does not keep the other from releasing its lock.
+    let guard = tokio::fs::File::create(&path)
+        .await
+        .unwrap()
+        .lock_write()
+        .await
+        .unwrap();
+    let release = async {
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        drop::<RwLockWriteGuard<_>>(guard);
+    };
+    let wait = async {
+        tokio::fs::File::create(&path)
+            .await
+            .unwrap()
+            .lock_write()
+            .await
+            .map(drop)
+    };
+    let ((), waited) = tokio::join!(release, wait);
+    assert!(waited.is_ok());
+}
@@ -0,0 +1,29 @@
+#![cfg(unix)]
+
+use async_fd_lock::blocking::LockWrite;
+use async_fd_lock::deadlock::{self, Detection};
+use std::fs::File;
+use std::sync::{Arc, Mutex};
+use tempfile::tempdir;
+
+// Kept apart from the other deadlock tests, since detection is global to the
+// process.
+
+#[test]
+fn reports_are_passed_to_hook() {
+    deadlock::set_detection(Detection::Report);
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let hook_reports = reports.clone();
+    deadlock::set_report_hook(move |report| hook_reports.lock().unwrap().push(report.clone()));
+    let dir = tempdir().unwrap();
+    let file = File::create(dir.path().join("foo.txt")).unwrap();
+
+    // With `flock`, a handle sharing the held lock does not wait for it, but
+    // is reported.
+    let shared = file.try_clone().unwrap();
+    let _guard = file.lock_write().unwrap();
+    let _shared = shared.lock_write().unwrap();
+    let reports = reports.lock().unwrap();
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].cycle().len(), 1);
+}
@@ -0,0 +1,74 @@
+use async_fd_lock::document::{Json, LockedDocument, Toml};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::thread;
+use tempfile::tempdir;
+
+type Counts = BTreeMap<String, u64>;
+
+#[test]
+fn json_round_trip() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("counts.json");
+    let document = LockedDocument::<Counts, Json>::new(&path);
+
+    let err = document.update(|_| ()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+
+    document
+        .update_or_default(|counts| counts.insert("cats".into(), 1))
+        .unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "{\n  \"cats\": 1\n}\n");
+
+    let previous = document
+        .update(|counts| counts.insert("cats".into(), 2))
+        .unwrap();
+    assert_eq!(previous, Some(1));
+    assert_eq!(document.read().unwrap()["cats"], 2);
+}
+
+#[test]
+fn toml_round_trip() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("counts.toml");
+    fs::write(&path, "cats = 3\n").unwrap();
+    let document = LockedDocument::<Counts, Toml>::new(&path);
+
+    document
+        .update(|counts| counts.insert("dogs".into(), 4))
+        .unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "cats = 3\ndogs = 4\n");
+}
+
+#[test]
+fn invalid_document() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("counts.json");
+    fs::write(&path, "not json").unwrap();
+    let document = LockedDocument::<Counts, Json>::new(&path);
+
+    assert_eq!(document.read().unwrap_err().kind(), ErrorKind::InvalidData);
+    let err = document.update(|_| ()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "not json");
+}
+
+#[test]
+fn concurrent_updates_are_not_lost() {
+    let dir = tempdir().unwrap();
+    let document = LockedDocument::<Counts, Json>::new(dir.path().join("counts.json"));
+
+    thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| {
+                for _ in 0..20 {
+                    document
+                        .update_or_default(|counts| *counts.entry("cats".into()).or_default() += 1)
+                        .unwrap();
+                }
+            });
+        }
+    });
+    assert_eq!(document.read().unwrap()["cats"], 160);
+}
@@ -0,0 +1,119 @@
+use async_fd_lock::dotlock::DotLock;
+use std::fs::{self, File};
+use std::io::ErrorKind;
+use std::thread;
+use std::time::Duration;
+use tempfile::tempdir;
+
+#[test]
+fn exclusive() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("data");
+    let dotlock = DotLock::new(&path);
+    assert_eq!(dotlock.lock_path(), dir.path().join("data.lock"));
+    assert_eq!(dotlock.owner().unwrap(), None);
+
+    let guard = dotlock
+        .try_lock_write(File::create(&path).unwrap())
+        .unwrap();
+    let owner = dotlock.owner().unwrap().unwrap();
+    assert_eq!(owner.pid, std::process::id());
+
+    let (file, err) = dotlock
+        .try_lock_write(File::open(&path).unwrap())
+        .unwrap_err()
+        .into();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+    thread::scope(|scope| {
+        let waiter = scope.spawn(|| {
+            DotLock::new(&path)
+                .retry_interval(Duration::from_millis(10))
+                .lock_write(file)
+                .unwrap()
+        });
+        thread::sleep(Duration::from_millis(50));
+        drop(guard);
+        waiter.join().unwrap().release().unwrap();
+    });
+    assert!(!dotlock.lock_path().exists());
+}
+
+#[test]
+fn break_expired_lock() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("data");
+    let dotlock = DotLock::new(&path).break_dead_owners(false);
+    fs::write(dotlock.lock_path(), "garbage").unwrap();
+
+    let (file, err) = dotlock
+        .try_lock_write(File::create(&path).unwrap())
+        .unwrap_err()
+        .into();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+    thread::sleep(Duration::from_millis(20));
+    let guard = dotlock
+        .stale_after(Duration::from_millis(10))
+        .try_lock_write(file)
+        .unwrap();
+    drop(guard);
+    assert!(!dir.path().join("data.lock").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn break_dead_owner() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("data");
+    let dotlock = DotLock::new(&path);
+
+    let child = std::process::Command::new("true").spawn().unwrap();
+    let pid = child.id();
+    child.wait_with_output().unwrap();
+
+    // Record the lock file as owned by the exited child.
+    let guard = dotlock
+        .try_lock_write(File::create(&path).unwrap())
+        .unwrap();
+    let contents = fs::read_to_string(dotlock.lock_path()).unwrap();
+    let contents = contents.replace(
+        &format!("pid={}", std::process::id()),
+        &format!("pid={pid}"),
+    );
+    std::mem::forget(guard);
+    fs::write(dotlock.lock_path(), contents).unwrap();
+
+    let guard = dotlock.try_lock_write(File::open(&path).unwrap()).unwrap();
+    assert_eq!(dotlock.owner().unwrap().unwrap().pid, std::process::id());
+    guard.release().unwrap();
+}
+
+#[test]
+fn release_broken_lock() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("data");
+    let dotlock = DotLock::new(&path);
+
+    let guard = dotlock
+        .try_lock_write(File::create(&path).unwrap())
+        .unwrap();
+    fs::remove_file(dotlock.lock_path()).unwrap();
+    assert!(guard.release().is_err());
+}
+
+#[test]
+fn empty_lock_file_is_not_stale() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("data");
+    let dotlock = DotLock::new(&path).stale_after(Duration::ZERO);
+    fs::write(dotlock.lock_path(), "").unwrap();
+    thread::sleep(Duration::from_millis(10));
+
+    let err = dotlock
+        .try_lock_write(File::create(&path).unwrap())
+        .unwrap_err();
+    assert_eq!(err.error.kind(), ErrorKind::WouldBlock);
+    // Neither the private nor the break files are left behind.
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+}
@@ -0,0 +1,54 @@
+#![cfg(feature = "async")]
+
+use async_fd_lock::election::LeaderElection;
+use std::io::{Seek, SeekFrom, Write};
+use std::time::Duration;
+use tempfile::tempdir;
+
+#[tokio::test]
+async fn single_leader() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("leader");
+    let replicas =
+        [0, 1].map(|_| LeaderElection::new(&path).retry_interval(Duration::from_millis(5)));
+    let mut watches = replicas.each_ref().map(LeaderElection::watch);
+
+    let mut first = replicas[0].campaign().await.unwrap();
+    first.guard_mut().seek(SeekFrom::Start(0)).unwrap();
+    first.guard_mut().write_all(b"replica 0").unwrap();
+    assert!(replicas[0].is_leader());
+    assert!(watches[0].has_changed().unwrap());
+
+    let second = tokio::spawn({
+        let replica = replicas[1].clone();
+        async move { replica.campaign().await.unwrap() }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!second.is_finished());
+    assert!(!replicas[1].is_leader());
+
+    first.step_down().unwrap();
+    watches[0].changed().await.unwrap();
+    assert!(!*watches[0].borrow());
+
+    watches[1].changed().await.unwrap();
+    assert!(*watches[1].borrow());
+    let second = second.await.unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "replica 0");
+
+    drop(second);
+    assert!(!replicas[1].is_leader());
+}
+
+#[tokio::test]
+async fn leadership_returns_after_drop() {
+    let dir = tempdir().unwrap();
+    let election = LeaderElection::new(dir.path().join("leader"));
+
+    let leadership = election.campaign().await.unwrap();
+    drop(leadership);
+    assert!(!election.is_leader());
+    let leadership = election.campaign().await.unwrap();
+    assert!(election.is_leader());
+    leadership.step_down().unwrap();
+}
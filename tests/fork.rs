@@ -0,0 +1,198 @@
+#![cfg(unix)]
+
+use async_fd_lock::blocking::LockWrite;
+use async_fd_lock::fork;
+use std::fs::File;
+use std::io::ErrorKind;
+use std::os::fd::AsRawFd;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::process::Command;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use tempfile::tempdir;
+
+/// Serializes tests, since forking while another thread holds the registry
+/// of locks would leave it locked in the child, and the close-on-exec option
+/// is global to the process.
+static SERIAL: Mutex<()> = Mutex::new(());
+
+fn serial(cloexec: bool) -> MutexGuard<'static, ()> {
+    let guard = SERIAL.lock().unwrap_or_else(PoisonError::into_inner);
+    fork::set_cloexec(cloexec);
+    guard
+}
+
+/// Opens `path` without close-on-exec, like a descriptor adopted from
+/// elsewhere.
+fn inheritable(path: &Path) -> File {
+    let file = File::create(path).unwrap();
+    unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETFD, 0) };
+    file
+}
+
+fn is_locked(path: &Path) -> bool {
+    match File::create(path).unwrap().try_lock_write() {
+        Ok(_guard) => false,
+        Err(err) if err.error.kind() == ErrorKind::WouldBlock => true,
+        Err(err) => panic!("{:?}", err.error),
+    }
+}
+
+/// Forks a child that runs `child` and reports its result, or -1 if it
+/// panicked. The child then exits right away if `exit` is set, without
+/// releasing any locks, or otherwise lives until killed.
+fn forked(exit: bool, child: impl FnOnce() -> i32) -> (libc::pid_t, i32) {
+    let mut fds = [0; 2];
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+    let pid = unsafe { libc::fork() };
+    assert!(pid >= 0);
+    if pid == 0 {
+        let value = panic::catch_unwind(AssertUnwindSafe(child)).unwrap_or(-1);
+        unsafe {
+            libc::write(fds[1], value.to_ne_bytes().as_ptr().cast(), 4);
+            if !exit {
+                libc::sleep(10);
+            }
+            libc::_exit(0);
+        }
+    }
+    let mut value = [0u8; 4];
+    unsafe {
+        libc::close(fds[1]);
+        assert_eq!(libc::read(fds[0], value.as_mut_ptr().cast(), 4), 4);
+        libc::close(fds[0]);
+    }
+    (pid, i32::from_ne_bytes(value))
+}
+
+fn wait(pid: libc::pid_t) {
+    unsafe { libc::waitpid(pid, std::ptr::null_mut(), 0) };
+}
+
+fn kill(pid: libc::pid_t) {
+    unsafe { libc::kill(pid, libc::SIGKILL) };
+}
+
+/// Locks `path` in a child that runs `sleep` and exits, returning the ID of
+/// the `sleep` process.
+fn lock_and_exec(path: &Path) -> libc::pid_t {
+    let (pid, sleeper) = forked(true, || {
+        let guard = inheritable(path).lock_write().unwrap();
+        // Reaped by init once this child exits.
+        #[allow(clippy::zombie_processes)]
+        let sleeper = Command::new("sleep").arg("10").spawn().unwrap();
+        // Exit while holding the lock.
+        std::mem::forget(guard);
+        sleeper.id() as i32
+    });
+    wait(pid);
+    assert!(sleeper > 0);
+    sleeper
+}
+
+#[test]
+fn exec_inherits_lock_without_cloexec() {
+    let _serial = serial(false);
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("foo.txt");
+
+    // The lock outlives the process that acquired it.
+    let sleeper = lock_and_exec(&path);
+    assert!(is_locked(&path));
+    kill(sleeper);
+}
+
+#[test]
+fn exec_does_not_inherit_lock_with_cloexec() {
+    let _serial = serial(true);
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("foo.txt");
+
+    let guard = inheritable(&path).lock_write().unwrap();
+    let flags = unsafe { libc::fcntl(guard.inner().as_raw_fd(), libc::F_GETFD) };
+    assert_ne!(flags & libc::FD_CLOEXEC, 0);
+    guard.release().unwrap();
+
+    let sleeper = lock_and_exec(&path);
+    assert!(!is_locked(&path));
+    kill(sleeper);
+}
+
+#[test]
+fn fork_relinquish_detaches_child() {
+    let _serial = serial(false);
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("foo.txt");
+
+    let (pid, grandchild) = forked(true, || {
+        let guard = File::create(&path).unwrap().lock_write().unwrap();
+        let (grandchild, relinquished) = forked(false, || fork::relinquish().unwrap() as i32);
+        assert_eq!(relinquished, 1);
+        assert_eq!(fork::relinquish().unwrap(), 0);
+        std::mem::forget(guard);
+        grandchild
+    });
+    wait(pid);
+    assert!(grandchild > 0);
+    assert!(!is_locked(&path));
+    kill(grandchild);
+}
+
+#[test]
+fn fork_child_drop_keeps_parent_lock() {
+    let _serial = serial(false);
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("foo.txt");
+
+    let mut guard = Some(File::create(&path).unwrap().lock_write().unwrap());
+    let (pid, _) = forked(false, || {
+        drop(guard.take());
+        0
+    });
+    // Dropping the inherited guard in the child did not release the lock.
+    assert!(is_locked(&path));
+    kill(pid);
+    wait(pid);
+    drop(guard);
+    assert!(!is_locked(&path));
+}
+
+#[test]
+fn fork_relinquish_leaves_split_file_open() {
+    let _serial = serial(false);
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("foo.txt");
+
+    let (pid, grandchild) = forked(true, || {
+        let guard = File::create(&path).unwrap().lock_write().unwrap();
+        let parts = guard.into_parts().unwrap();
+        // Only the duplicate held by the lock is detached.
+        let (grandchild, relinquished) = forked(false, || fork::relinquish().unwrap() as i32);
+        assert_eq!(relinquished, 1);
+        std::mem::forget(parts);
+        grandchild
+    });
+    wait(pid);
+    assert!(grandchild > 0);
+    assert!(is_locked(&path));
+    kill(grandchild);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn fork_relinquish_leaves_lock_files_alone() {
+    use async_fd_lock::LockStrategy;
+
+    let _serial = serial(false);
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("foo.txt");
+
+    let guard = File::create(&path)
+        .unwrap()
+        .lock_write_with(LockStrategy::DotLock)
+        .unwrap();
+    let (pid, relinquished) = forked(true, || fork::relinquish().unwrap() as i32);
+    wait(pid);
+    assert_eq!(relinquished, 0);
+    guard.release().unwrap();
+}
@@ -0,0 +1,113 @@
+use async_fd_lock::blocking::{LockRead, LockWrite};
+use async_fd_lock::hierarchy::{self, LevelViolation, Violation};
+use std::fs::File;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread;
+use tempfile::{tempdir, TempDir};
+
+/// Serializes tests, since the violation mode is global to the process.
+static MODE: Mutex<()> = Mutex::new(());
+
+fn mode(violation: Violation) -> MutexGuard<'static, ()> {
+    let guard = MODE.lock().unwrap_or_else(PoisonError::into_inner);
+    hierarchy::set_violation(violation);
+    guard
+}
+
+fn leveled(dir: &TempDir, name: &str, level: u32) -> PathBuf {
+    let path = dir.path().join(name);
+    File::create(&path).unwrap();
+    hierarchy::set_level(&path, level).unwrap();
+    path
+}
+
+fn open(path: &Path) -> File {
+    File::options().read(true).write(true).open(path).unwrap()
+}
+
+#[test]
+fn lower_level_after_higher_fails() {
+    let _mode = mode(Violation::Error);
+    let dir = tempdir().unwrap();
+    let low = leveled(&dir, "low", 1);
+    let high = leveled(&dir, "high", 2);
+
+    let guard = open(&high).lock_read().unwrap();
+    let err = open(&low).try_lock_write().unwrap_err();
+    assert_eq!(err.error.kind(), ErrorKind::Deadlock);
+    let violation: &LevelViolation = err.error.get_ref().unwrap().downcast_ref().unwrap();
+    assert_eq!(violation.acquiring, low);
+    assert_eq!(violation.acquiring_level, 1);
+    assert_eq!(violation.holding, high);
+    assert_eq!(violation.holding_level, 2);
+
+    // The order only applies while the higher lock is held.
+    drop(guard);
+    let low_guard = open(&low).lock_write().unwrap();
+    let _high_guard = open(&high).lock_write().unwrap();
+    drop(low_guard);
+}
+
+#[test]
+fn owners_and_unleveled_files_are_independent() {
+    let _mode = mode(Violation::Error);
+    let dir = tempdir().unwrap();
+    let low = leveled(&dir, "low", 1);
+    let high = leveled(&dir, "high", 2);
+    let other = dir.path().join("other");
+
+    let _high = open(&high).lock_write().unwrap();
+    let _other = File::create(&other).unwrap().lock_write().unwrap();
+    let spawned = low.clone();
+    thread::spawn(move || open(&spawned).lock_write().map(drop).unwrap())
+        .join()
+        .unwrap();
+
+    hierarchy::clear_level(&low).unwrap();
+    let _low = open(&low).lock_write().unwrap();
+}
+
+#[test]
+#[should_panic(expected = "at level 1 while holding")]
+fn lower_level_after_higher_panics() {
+    let _mode = mode(Violation::Panic);
+    let dir = tempdir().unwrap();
+    let low = leveled(&dir, "low", 1);
+    let high = leveled(&dir, "high", 2);
+
+    let _high = open(&high).lock_write().unwrap();
+    let _low = open(&low).lock_write();
+}
+
+#[test]
+fn async_locks_are_only_checked_in_scopes() {
+    use async_fd_lock::reentrant;
+
+    let _mode = mode(Violation::Panic);
+    let dir = tempdir().unwrap();
+    let low = leveled(&dir, "low", 1);
+    let high = leveled(&dir, "high", 2);
+    let lock = |path: PathBuf| async move {
+        async_fd_lock::LockWrite::lock_write(tokio::fs::File::from_std(open(&path)))
+            .await
+            .map_err(|err| err.error)
+    };
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let _high = lock(high.clone()).await.unwrap();
+        let _low = lock(low.clone()).await.unwrap();
+    });
+    let task = runtime.spawn(reentrant::scope(async move {
+        let _high = lock(high).await.unwrap();
+        let _low = lock(low).await;
+    }));
+    let panic = runtime.block_on(task).unwrap_err().into_panic();
+    let message = panic.downcast_ref::<String>().unwrap();
+    assert!(message.contains("at level 1 while holding"), "{message}");
+}
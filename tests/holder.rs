@@ -0,0 +1,85 @@
+use async_fd_lock::blocking::{LockRead, LockWrite};
+use async_fd_lock::holder::{sidecar_path, Holder};
+use std::fs::{self, File};
+use tempfile::tempdir;
+
+#[test]
+fn contender_reads_holder() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("job");
+    let sidecar = dir.path().join("job.who");
+
+    let mut guard = File::create(&path).unwrap().lock_write().unwrap();
+    guard.publish_holder_at(&sidecar, "nightly backup").unwrap();
+
+    let err = File::open(&path).unwrap().try_lock_write().unwrap_err();
+    let holder = err.holder_at(&sidecar).unwrap().unwrap();
+    assert_eq!(holder.pid, std::process::id());
+    assert_eq!(holder.purpose, "nightly backup");
+    assert!(!holder.program.is_empty());
+
+    guard.release().unwrap();
+    assert!(!sidecar.exists());
+}
+
+#[test]
+fn sidecar_follows_the_lock() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("job");
+    let first = dir.path().join("first");
+    let second = dir.path().join("second");
+
+    let mut guard = File::create(&path).unwrap().lock_write().unwrap();
+    guard.publish_holder_at(&first, "one").unwrap();
+    guard.publish_holder_at(&second, "two").unwrap();
+    assert!(!first.exists());
+
+    // The record moves with the lock when the guard is split.
+    let guard = guard.map(std::io::BufWriter::new).unwrap();
+    assert_eq!(Holder::read(&second).unwrap().unwrap().purpose, "two");
+    drop(guard);
+    assert!(!second.exists());
+
+    // Records are only read when the lock is held by someone else.
+    let guard = File::open(&path).unwrap().lock_read().unwrap();
+    let err = File::open(&path).unwrap().try_lock_write().unwrap_err();
+    fs::write(&second, "not a holder record").unwrap();
+    assert_eq!(err.holder_at(&second).unwrap(), None);
+    drop(guard);
+}
+
+#[test]
+fn purpose_is_escaped() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("job");
+    let sidecar = sidecar_path(&path);
+    let purpose = "line one\nline two\\\r\npid=0";
+
+    let mut guard = File::create(&path).unwrap().lock_write().unwrap();
+    guard.publish_holder_at(&sidecar, purpose).unwrap();
+    let contents = fs::read_to_string(&sidecar).unwrap();
+    assert_eq!(contents.lines().next(), Some("async-fd-lock holder v1"));
+    assert_eq!(contents.lines().count(), 6);
+
+    let holder = Holder::read(&sidecar).unwrap().unwrap();
+    assert_eq!(holder.purpose, purpose);
+    assert_eq!(holder.pid, std::process::id());
+    drop(guard);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn default_sidecar_path() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("job");
+
+    let mut guard = File::create(&path).unwrap().lock_write().unwrap();
+    guard.publish_holder("deploy").unwrap();
+    assert!(dir.path().join("job.holder").exists());
+
+    let err = File::open(&path).unwrap().try_lock_write().unwrap_err();
+    assert_eq!(err.holder().unwrap().unwrap().purpose, "deploy");
+
+    guard.release().unwrap();
+    assert!(!dir.path().join("job.holder").exists());
+}
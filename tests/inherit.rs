@@ -0,0 +1,155 @@
+#![cfg(unix)]
+
+use async_fd_lock::blocking::LockWrite;
+use async_fd_lock::inherit::INHERITED_ENV;
+use async_fd_lock::{LockStrategy, RwLockWriteGuard};
+use std::fs::File;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use tempfile::tempdir;
+
+fn is_locked(path: &Path) -> bool {
+    let file = File::options().write(true).open(path).unwrap();
+    match file.try_lock_write() {
+        Ok(_guard) => false,
+        Err(err) if err.error.kind() == ErrorKind::WouldBlock => true,
+        Err(err) => panic!("{:?}", err.error),
+    }
+}
+
+/// Runs in the child spawned by `child_adopts_lock`, and does nothing
+/// otherwise.
+#[test]
+fn adopting_child() {
+    if std::env::var_os(INHERITED_ENV).is_none() {
+        return;
+    }
+    let mut guard = RwLockWriteGuard::from_inherited().unwrap();
+    assert_eq!(guard.strategy(), LockStrategy::Flock);
+    let err = RwLockWriteGuard::from_inherited().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+    guard.write_all(b"child").unwrap();
+    println!("adopted");
+    // Hold the lock until the parent is done checking.
+    std::io::stdin().read_line(&mut String::new()).unwrap();
+    guard.release().unwrap();
+}
+
+#[test]
+fn child_adopts_lock() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("foo.txt");
+
+    let guard = File::create(&path).unwrap().lock_write().unwrap();
+    let (file, lock) = guard.into_inheritable().unwrap();
+    // Only the command the lock is passed to inherits it.
+    let flags = unsafe { libc::fcntl(lock.as_raw_fd(), libc::F_GETFD) };
+    assert_ne!(flags & libc::FD_CLOEXEC, 0);
+    let mut child = lock
+        .pass_to(&mut Command::new(std::env::current_exe().unwrap()))
+        .args(["--exact", "adopting_child", "--nocapture", "--quiet"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    drop(file);
+
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    while line.trim() != "adopted" {
+        line.clear();
+        assert_ne!(stdout.read_line(&mut line).unwrap(), 0, "child exited");
+    }
+    // The lock outlives this process's handles.
+    assert!(is_locked(&path));
+    child.stdin.take().unwrap().write_all(b"\n").unwrap();
+    assert!(child.wait().unwrap().success());
+    assert!(!is_locked(&path));
+    assert_eq!(std::fs::read(&path).unwrap(), b"child");
+}
+
+#[tokio::test]
+async fn tokio_child_adopts_lock() {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("foo.txt");
+
+    let guard = File::create(&path).unwrap().lock_write().unwrap();
+    let (file, lock) = guard.into_inheritable().unwrap();
+    let mut command = tokio::process::Command::new(std::env::current_exe().unwrap());
+    lock.pass_to(command.as_std_mut());
+    let mut child = command
+        .args(["--exact", "adopting_child", "--nocapture", "--quiet"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    drop((file, command));
+
+    let mut stdout = tokio::io::BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    while line.trim() != "adopted" {
+        line.clear();
+        assert_ne!(
+            stdout.read_line(&mut line).await.unwrap(),
+            0,
+            "child exited"
+        );
+    }
+    assert!(is_locked(&path));
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(b"\n").await.unwrap();
+    assert!(child.wait().await.unwrap().success());
+    assert!(!is_locked(&path));
+}
+
+#[test]
+fn only_open_file_locks_are_inheritable() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("foo.txt");
+
+    let guard = File::create(&path)
+        .unwrap()
+        .lock_write_with(LockStrategy::DotLock)
+        .unwrap();
+    let err = guard.into_inheritable().unwrap_err();
+    assert_eq!(err.error.kind(), ErrorKind::Unsupported);
+    err.file.release().unwrap();
+
+    if std::env::var_os(INHERITED_ENV).is_none() {
+        let err = RwLockWriteGuard::from_inherited().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+    }
+}
+
+/// Runs in the children spawned by `invalid_locks_are_rejected`, and does
+/// nothing otherwise.
+#[test]
+fn rejecting_child() {
+    if std::env::var_os(INHERITED_ENV).is_none() {
+        return;
+    }
+    let err = RwLockWriteGuard::from_inherited().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn invalid_locks_are_rejected() {
+    for value in ["1:flock", "999:flock", "x:flock"] {
+        let status = Command::new(std::env::current_exe().unwrap())
+            .env(INHERITED_ENV, value)
+            .args(["--exact", "rejecting_child", "--quiet"])
+            .stdout(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success(), "{value}");
+    }
+
+    let dir = tempdir().unwrap();
+    let file = File::create(dir.path().join("foo.txt")).unwrap();
+    let err = RwLockWriteGuard::adopt(file, LockStrategy::DotLock).unwrap_err();
+    assert_eq!(err.error.kind(), ErrorKind::Unsupported);
+}
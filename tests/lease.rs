@@ -0,0 +1,82 @@
+use async_fd_lock::blocking::LockWrite;
+use async_fd_lock::lease::{self, Lease, LeaseStatus};
+use std::fs::File;
+use std::io::{Seek, SeekFrom, Write};
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+use tempfile::tempdir;
+
+#[test]
+fn heartbeat_keeps_lease_fresh() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+
+    assert_eq!(
+        lease::probe(&path).unwrap_err().kind(),
+        std::io::ErrorKind::NotFound
+    );
+
+    let guard = File::create(&path).unwrap().lock_write().unwrap();
+    let lease = Lease::new(guard, Duration::from_millis(20)).unwrap();
+    let LeaseStatus::Fresh(first) = lease::probe(&path).unwrap() else {
+        panic!("lease should be fresh");
+    };
+    assert_eq!(first.pid, std::process::id());
+    assert_eq!(first.interval, Duration::from_millis(20));
+
+    thread::sleep(Duration::from_millis(200));
+    let LeaseStatus::Fresh(second) = lease::probe(&path).unwrap() else {
+        panic!("lease should still be fresh");
+    };
+    assert!(second.at > first.at);
+
+    let guard = lease.into_guard();
+    thread::sleep(Duration::from_millis(200));
+    assert!(matches!(
+        lease::probe(&path).unwrap(),
+        LeaseStatus::Stale(_)
+    ));
+
+    drop(guard);
+    assert_eq!(lease::probe(&path).unwrap(), LeaseStatus::Free);
+}
+
+#[test]
+fn holder_without_heartbeat() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+
+    let mut guard = File::create(&path).unwrap().lock_write().unwrap();
+    assert_eq!(lease::probe(&path).unwrap(), LeaseStatus::Unknown);
+
+    guard.seek(SeekFrom::Start(1)).unwrap();
+    writeln!(guard, "async-fd-lock lease v1 pid=1 at=1000 every=1000").unwrap();
+    let LeaseStatus::Stale(heartbeat) = lease::probe(&path).unwrap() else {
+        panic!("lease should be stale");
+    };
+    assert_eq!(heartbeat.pid, 1);
+    assert_eq!(heartbeat.at, UNIX_EPOCH + Duration::from_secs(1));
+}
+
+#[cfg(feature = "async")]
+#[tokio::test(flavor = "current_thread")]
+async fn heartbeat_runs_on_runtime() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+
+    let guard = File::create(&path).unwrap().lock_write().unwrap();
+    let lease = Lease::new(guard, Duration::from_millis(20)).unwrap();
+    let LeaseStatus::Fresh(first) = lease::probe(&path).unwrap() else {
+        panic!("lease should be fresh");
+    };
+
+    // Beats only while the runtime gets to run.
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let LeaseStatus::Fresh(second) = lease::probe(&path).unwrap() else {
+        panic!("lease should still be fresh");
+    };
+    assert!(second.at > first.at);
+
+    lease.release().unwrap();
+    assert_eq!(lease::probe(&path).unwrap(), LeaseStatus::Free);
+}
@@ -0,0 +1,108 @@
+use async_fd_lock::blocking::{LockRead, LockWrite};
+use async_fd_lock::RwLockWriteGuard;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Read, Write};
+use tempfile::tempdir;
+
+#[test]
+fn map_keeps_lock_held() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+    fs::write(&path, "first\nsecond\n").unwrap();
+
+    let mut guard = File::open(&path)
+        .unwrap()
+        .lock_read()
+        .unwrap()
+        .map(BufReader::new)
+        .unwrap();
+    let mut line = String::new();
+    guard.read_line(&mut line).unwrap();
+    assert_eq!(line, "first\n");
+
+    let err = File::open(&path).unwrap().try_lock_write().unwrap_err();
+    assert_eq!(err.error.kind(), ErrorKind::WouldBlock);
+
+    drop(guard);
+    let _guard = File::open(&path).unwrap().try_lock_write().unwrap();
+}
+
+#[test]
+fn mapped_writer_flushes_before_unlocking() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+
+    let mut guard = File::create(&path)
+        .unwrap()
+        .lock_write()
+        .unwrap()
+        .map(BufWriter::new)
+        .unwrap();
+    guard.write_all(b"bongo cat").unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"");
+    drop(guard);
+
+    let mut contents = String::new();
+    File::open(&path)
+        .unwrap()
+        .try_lock_read()
+        .unwrap()
+        .read_to_string(&mut contents)
+        .unwrap();
+    assert_eq!(contents, "bongo cat");
+}
+
+#[test]
+fn release_flushes_before_unlocking() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+
+    let mut guard = File::create(&path)
+        .unwrap()
+        .lock_write()
+        .unwrap()
+        .map(BufWriter::new)
+        .unwrap();
+    guard.write_all(b"bongo cat").unwrap();
+    let writer = guard.release().unwrap();
+    assert!(writer.buffer().is_empty());
+    assert_eq!(fs::read(&path).unwrap(), b"bongo cat");
+}
+
+#[test]
+fn try_map_releases_on_error() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+
+    let err = File::create(&path)
+        .unwrap()
+        .lock_write()
+        .unwrap()
+        .try_map(|_| Err::<(), _>(std::io::Error::other("decoder failed")))
+        .unwrap_err();
+    assert_eq!(err.to_string(), "decoder failed");
+
+    let _guard = File::open(&path).unwrap().try_lock_write().unwrap();
+}
+
+#[test]
+fn parts_round_trip() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+
+    let (file, lock) = File::create(&path)
+        .unwrap()
+        .lock_write()
+        .unwrap()
+        .into_parts()
+        .unwrap();
+    let err = File::open(&path).unwrap().try_lock_read().unwrap_err();
+    assert_eq!(err.error.kind(), ErrorKind::WouldBlock);
+
+    let guard = RwLockWriteGuard::from_parts(file, lock);
+    let err = File::open(&path).unwrap().try_lock_read().unwrap_err();
+    assert_eq!(err.error.kind(), ErrorKind::WouldBlock);
+
+    guard.release().unwrap();
+    let _guard = File::open(&path).unwrap().try_lock_read().unwrap();
+}
@@ -0,0 +1,39 @@
+use async_fd_lock::blocking::{LockRead, LockWrite};
+use std::fs::{self, File, OpenOptions};
+use std::io::ErrorKind;
+use tempfile::tempdir;
+
+#[test]
+fn map_readonly() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+    fs::write(&path, "bongo cat").unwrap();
+
+    let guard = File::open(&path).unwrap().lock_read().unwrap();
+    let map = unsafe { guard.map_readonly() }.unwrap();
+    assert_eq!(&*map, b"bongo cat");
+
+    let err = File::open(&path).unwrap().try_lock_write().unwrap_err();
+    assert_eq!(err.error.kind(), ErrorKind::WouldBlock);
+}
+
+#[test]
+fn map_mut_flushes_before_release() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+    fs::write(&path, "bongo cat").unwrap();
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&path)
+        .unwrap();
+    let mut guard = file.lock_write().unwrap();
+    let mut map = unsafe { guard.map_mut() }.unwrap();
+    map[..5].copy_from_slice(b"hello");
+    drop(map);
+    guard.release().unwrap();
+
+    let guard = File::open(&path).unwrap().try_lock_read().unwrap();
+    assert_eq!(&*unsafe { guard.map_readonly() }.unwrap(), b"hello cat");
+}
@@ -0,0 +1,80 @@
+use async_fd_lock::named::NamedLock;
+use std::io::ErrorKind;
+use tempfile::tempdir;
+
+#[test]
+fn names_map_to_distinct_files() {
+    let dir = tempdir().unwrap();
+    let file_name = |name: &str| {
+        let lock = NamedLock::new(dir.path(), name);
+        assert_eq!(lock.path().parent(), Some(dir.path()));
+        lock.path()
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_owned()
+    };
+
+    assert_eq!(file_name("migrations"), "migrations.lock");
+    assert_eq!(file_name("a/b c"), "a%2Fb%20c.lock");
+    assert_eq!(file_name(".."), "%2E..lock");
+    assert_eq!(file_name(""), "%.lock");
+    assert_eq!(file_name("%"), "%25.lock");
+    assert_eq!(file_name("ünï"), "%C3%BCn%C3%AF.lock");
+    assert_eq!(file_name("nul"), "%6Eul.lock");
+    assert_eq!(file_name("Com1.txt"), "%43om1.txt.lock");
+
+    let long = "x".repeat(1000);
+    let longer = "x".repeat(1001);
+    assert_eq!(file_name(&long).len(), 200);
+    assert_ne!(file_name(&long), file_name(&longer));
+    assert_eq!(file_name(&long), file_name(&long));
+}
+
+#[test]
+fn guards_exclude() {
+    let dir = tempdir().unwrap();
+    let lock = NamedLock::new(dir.path().join("nested/namespace"), "migrations");
+
+    let first = lock.lock_read().unwrap();
+    let second = lock.try_lock_read().unwrap();
+    let err = lock.try_lock_write().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+    drop(first);
+    second.release().unwrap();
+
+    let guard = lock.try_lock_write().unwrap();
+    assert_eq!(
+        lock.try_lock_read().unwrap_err().kind(),
+        ErrorKind::WouldBlock
+    );
+    NamedLock::new(lock.path().parent().unwrap(), "other")
+        .try_lock_write()
+        .unwrap()
+        .release()
+        .unwrap();
+    guard.release().unwrap();
+}
+
+#[tokio::test]
+async fn async_guards() {
+    let dir = tempdir().unwrap();
+    let lock = NamedLock::new(dir.path(), "jobs/42");
+
+    let guard = lock.lock_write_async().await.unwrap();
+    let err = lock.try_lock_read_async().await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+    let waiter = tokio::spawn({
+        let lock = lock.clone();
+        async move { lock.lock_read_async().await.unwrap().release().unwrap() }
+    });
+    guard.release().unwrap();
+    waiter.await.unwrap();
+    lock.try_lock_write_async()
+        .await
+        .unwrap()
+        .release()
+        .unwrap();
+}
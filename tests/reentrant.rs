@@ -0,0 +1,131 @@
+use async_fd_lock::blocking::LockWrite;
+use async_fd_lock::reentrant::{Owner, Reentrant};
+use std::fs::File;
+use std::io::ErrorKind;
+use std::thread;
+use tempfile::tempdir;
+
+#[test]
+fn nested_guards_keep_the_lock() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("foo.txt");
+    let reentrant = Reentrant::new();
+
+    let outer = reentrant.lock_write(File::create(&path).unwrap()).unwrap();
+    let inner = reentrant.lock_write(File::create(&path).unwrap()).unwrap();
+    let read = reentrant.try_lock_read(File::open(&path).unwrap()).unwrap();
+    assert_eq!(inner.owner(), Owner::current());
+
+    // Releasing the outermost guard first keeps the lock for the others.
+    outer.release().unwrap();
+    drop(read);
+    let err = File::create(&path).unwrap().try_lock_write().unwrap_err();
+    assert_eq!(err.error.kind(), ErrorKind::WouldBlock);
+    drop(inner);
+    let _guard = File::create(&path).unwrap().try_lock_write().unwrap();
+}
+
+#[test]
+fn owners_are_separate() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("foo.txt");
+    let reentrant = Reentrant::new();
+
+    let _guard = reentrant.lock_write(File::create(&path).unwrap()).unwrap();
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            let err = reentrant
+                .try_lock_write(File::create(&path).unwrap())
+                .unwrap_err();
+            assert_eq!(err.error.kind(), ErrorKind::WouldBlock);
+        });
+    });
+}
+
+#[test]
+fn shared_locks_cannot_upgrade() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("foo.txt");
+    let reentrant = Reentrant::new();
+
+    File::create(&path).unwrap();
+    let read = reentrant.lock_read(File::open(&path).unwrap()).unwrap();
+    let err = reentrant
+        .lock_write(File::create(&path).unwrap())
+        .unwrap_err();
+    assert_eq!(err.error.kind(), ErrorKind::Deadlock);
+    read.release().unwrap();
+    reentrant
+        .lock_write(File::create(&path).unwrap())
+        .unwrap()
+        .release()
+        .unwrap();
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn scopes_own_locks() {
+    use async_fd_lock::reentrant::scope;
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("foo.txt");
+    let reentrant = Reentrant::new();
+
+    let outer = scope(async {
+        let outer = reentrant
+            .lock_write_async(File::create(&path).unwrap())
+            .await
+            .unwrap();
+        assert!(matches!(outer.owner(), Owner::Scope(_)));
+        tokio::task::yield_now().await;
+        let inner = reentrant
+            .lock_write_async(File::create(&path).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(inner.owner(), outer.owner());
+        inner.release().unwrap();
+        outer
+    })
+    .await;
+
+    // Another scope is another owner.
+    scope(async {
+        let err = reentrant
+            .try_lock_write(File::create(&path).unwrap())
+            .unwrap_err();
+        assert_eq!(err.error.kind(), ErrorKind::WouldBlock);
+    })
+    .await;
+    outer.release().unwrap();
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn tasks_on_one_thread_are_separate_owners() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("foo.txt");
+    let reentrant = Reentrant::new();
+
+    let path1 = path.clone();
+    let guard = tokio::spawn(async move {
+        reentrant
+            .lock_write_async(File::create(&path1).unwrap())
+            .await
+            .unwrap()
+    })
+    .await
+    .unwrap();
+    assert!(matches!(guard.owner(), Owner::Task(_)));
+    // Runs on the same thread of the current-thread runtime.
+    let path2 = path.clone();
+    let err = tokio::spawn(async move {
+        reentrant
+            .try_lock_write(File::create(&path2).unwrap())
+            .unwrap_err()
+            .error
+    })
+    .await
+    .unwrap();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+    guard.release().unwrap();
+}
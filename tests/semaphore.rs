@@ -0,0 +1,81 @@
+use async_fd_lock::semaphore::FileSemaphore;
+use std::collections::BTreeSet;
+use std::io::ErrorKind;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tempfile::tempdir;
+
+#[test]
+fn holds_distinct_slots() {
+    let dir = tempdir().unwrap();
+    let semaphore = FileSemaphore::new(dir.path().join("jobs"), 3);
+
+    let mut guards = (0..3)
+        .map(|_| semaphore.try_acquire().unwrap())
+        .collect::<Vec<_>>();
+    let slots = guards
+        .iter()
+        .map(|guard| guard.slot())
+        .collect::<BTreeSet<_>>();
+    assert_eq!(slots, BTreeSet::from([0, 1, 2]));
+    assert!(semaphore.slot_path(2).exists());
+    assert!(!semaphore.slot_path(3).exists());
+
+    let err = semaphore.try_acquire().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+    let freed = guards.remove(1);
+    let slot = freed.slot();
+    freed.release().unwrap();
+    assert_eq!(semaphore.try_acquire().unwrap().slot(), slot);
+}
+
+#[test]
+fn caps_concurrency() {
+    let dir = tempdir().unwrap();
+    let semaphore = FileSemaphore::new(dir.path(), 2)
+        .backoff(Duration::from_millis(1), Duration::from_millis(5));
+    let running = Arc::new(AtomicUsize::new(0));
+    let peak = Arc::new(AtomicUsize::new(0));
+
+    let workers = (0..6)
+        .map(|_| {
+            let (semaphore, running, peak) = (semaphore.clone(), running.clone(), peak.clone());
+            thread::spawn(move || {
+                let guard = semaphore.acquire().unwrap();
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(20));
+                running.fetch_sub(1, Ordering::SeqCst);
+                guard.release().unwrap();
+            })
+        })
+        .collect::<Vec<_>>();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    assert_eq!(peak.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn async_waits_for_a_slot() {
+    let dir = tempdir().unwrap();
+    let semaphore = FileSemaphore::new(dir.path(), 1)
+        .backoff(Duration::from_millis(1), Duration::from_millis(5));
+
+    let guard = semaphore.acquire_async().await.unwrap();
+    assert_eq!(guard.slot(), 0);
+    let err = semaphore.try_acquire_async().await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+    let waiter = tokio::spawn({
+        let semaphore = semaphore.clone();
+        async move { semaphore.acquire_async().await.unwrap().slot() }
+    });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(!waiter.is_finished());
+    guard.release().unwrap();
+    assert_eq!(waiter.await.unwrap(), 0);
+}
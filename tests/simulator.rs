@@ -0,0 +1,152 @@
+use async_fd_lock::blocking::{LockRead, LockWrite};
+use async_fd_lock::testing::{Event, Fault, Simulator};
+use std::fs::File;
+use std::io::{self, ErrorKind};
+use std::thread;
+use tempfile::tempdir;
+
+#[test]
+fn inject_faults() {
+    let dir = tempdir().unwrap();
+    let file = File::create(dir.path().join("lockfile")).unwrap();
+    let simulator = Simulator::attach(&file).unwrap();
+
+    simulator.inject(Fault::Interrupted);
+    simulator.inject(Fault::NoLocks);
+    simulator.inject(Fault::Io);
+
+    let (file, err) = file.lock_write().unwrap_err().into();
+    assert_eq!(err.kind(), ErrorKind::Interrupted);
+    let (file, err) = file.try_lock_read().unwrap_err().into();
+    assert_eq!(
+        err.raw_os_error(),
+        io::Error::from(Fault::NoLocks).raw_os_error()
+    );
+    let (file, err) = file.lock_read().unwrap_err().into();
+    assert_eq!(
+        err.raw_os_error(),
+        io::Error::from(Fault::Io).raw_os_error()
+    );
+
+    let guard = file.lock_write().unwrap();
+    guard.release().unwrap();
+
+    assert_eq!(
+        simulator.history(),
+        [
+            Event::Failed {
+                write: true,
+                kind: ErrorKind::Interrupted
+            },
+            Event::Failed {
+                write: false,
+                kind: io::Error::from(Fault::NoLocks).kind()
+            },
+            Event::Failed {
+                write: false,
+                kind: io::Error::from(Fault::Io).kind()
+            },
+            Event::Acquired { write: true },
+            Event::Released,
+        ]
+    );
+}
+
+#[test]
+fn scripted_contention() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+    let file = File::create(&path).unwrap();
+    let simulator = Simulator::attach(&file).unwrap();
+
+    let hold = simulator.hold_read().unwrap();
+    let _guard = File::open(&path).unwrap().try_lock_read().unwrap();
+    let (file, err) = file.try_lock_write().unwrap_err().into();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+    assert_eq!(
+        simulator.hold_write().unwrap_err().kind(),
+        ErrorKind::WouldBlock
+    );
+    drop(_guard);
+
+    thread::scope(|scope| {
+        let waiter = scope.spawn(|| file.lock_write().unwrap());
+        simulator.wait_blocked(1);
+        hold.release();
+        waiter.join().unwrap().release().unwrap();
+    });
+}
+
+#[test]
+fn step_through_acquisitions() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+    let first = File::create(&path).unwrap();
+    let second = File::open(&path).unwrap();
+    let simulator = Simulator::attach(&first).unwrap();
+
+    simulator.pause();
+    thread::scope(|scope| {
+        let first = scope.spawn(|| first.lock_write().unwrap());
+        simulator.wait_queued(1);
+        let second = scope.spawn(|| second.lock_read().unwrap());
+        simulator.wait_queued(2);
+
+        assert!(simulator.step());
+        simulator.wait_queued(1);
+        let first = first.join().unwrap();
+
+        assert!(simulator.step());
+        simulator.wait_blocked(1);
+        assert!(!simulator.step());
+        drop(first);
+
+        let _second = second.join().unwrap();
+    });
+
+    assert_eq!(
+        simulator.history(),
+        [
+            Event::Acquired { write: true },
+            Event::Released,
+            Event::Acquired { write: false },
+            Event::Released,
+        ]
+    );
+}
+
+#[tokio::test]
+async fn async_acquisition() {
+    use async_fd_lock::LockWrite;
+
+    let dir = tempdir().unwrap();
+    let file = tokio::fs::File::create(dir.path().join("lockfile"))
+        .await
+        .unwrap();
+    let simulator = Simulator::attach(&file).unwrap();
+
+    let hold = simulator.hold_write().unwrap();
+    let waiter = tokio::spawn(file.lock_write());
+    while simulator.blocked() == 0 {
+        tokio::task::yield_now().await;
+    }
+    hold.release();
+
+    let guard = waiter.await.unwrap().unwrap();
+    drop(guard);
+    assert_eq!(
+        simulator.history(),
+        [Event::Acquired { write: true }, Event::Released]
+    );
+}
+
+#[test]
+fn attach_twice() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+    let file = File::create(&path).unwrap();
+    let _simulator = Simulator::attach(&file).unwrap();
+
+    let err = Simulator::attach(&File::open(&path).unwrap()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+}
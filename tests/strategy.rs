@@ -0,0 +1,92 @@
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use async_fd_lock::blocking::LockRead;
+use async_fd_lock::blocking::LockWrite;
+use async_fd_lock::LockStrategy;
+use std::fs::File;
+use std::io::ErrorKind;
+use tempfile::tempdir;
+
+#[test]
+fn auto_reports_chosen_strategy() {
+    let dir = tempdir().unwrap();
+    let file = File::create(dir.path().join("lockfile")).unwrap();
+
+    let guard = file.lock_write_with(LockStrategy::Auto).unwrap();
+    assert_ne!(guard.strategy(), LockStrategy::Auto);
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn fcntl_excludes_other_handles() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+
+    let g0 = File::create(&path)
+        .unwrap()
+        .lock_write_with(LockStrategy::Fcntl)
+        .unwrap();
+    assert_eq!(g0.strategy(), LockStrategy::Fcntl);
+
+    let l1 = File::open(&path).unwrap();
+    let (l1, err) = l1
+        .try_lock_read_with(LockStrategy::Fcntl)
+        .unwrap_err()
+        .into();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+    drop(g0);
+
+    let g1 = l1.try_lock_read_with(LockStrategy::Fcntl).unwrap();
+    let _g2 = File::open(&path)
+        .unwrap()
+        .try_lock_read_with(LockStrategy::Fcntl)
+        .unwrap();
+    drop(g1);
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+#[test]
+fn fcntl_is_unsupported() {
+    let dir = tempdir().unwrap();
+    let file = File::create(dir.path().join("lockfile")).unwrap();
+
+    let err = file.lock_write_with(LockStrategy::Fcntl).unwrap_err();
+    assert_eq!(err.error.kind(), ErrorKind::Unsupported);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn dotlock() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+
+    let guard = File::create(&path)
+        .unwrap()
+        .try_lock_write_with(LockStrategy::DotLock)
+        .unwrap();
+    assert_eq!(guard.strategy(), LockStrategy::DotLock);
+    assert!(dir.path().join("lockfile.lock").exists());
+
+    let (_, err) = File::open(&path)
+        .unwrap()
+        .try_lock_read_with(LockStrategy::DotLock)
+        .unwrap_err()
+        .into();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+    guard.release().unwrap();
+    assert!(!dir.path().join("lockfile.lock").exists());
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[tokio::test]
+async fn async_strategy() {
+    use async_fd_lock::LockWrite;
+
+    let dir = tempdir().unwrap();
+    let file = tokio::fs::File::create(dir.path().join("lockfile"))
+        .await
+        .unwrap();
+
+    let guard = file.lock_write_with(LockStrategy::Fcntl).await.unwrap();
+    assert_eq!(guard.strategy(), LockStrategy::Fcntl);
+}
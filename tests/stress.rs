@@ -0,0 +1,53 @@
+use async_fd_lock::testing::stress::{Backend, Stress};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use async_fd_lock::LockStrategy;
+
+#[test]
+fn blocking_cross_process() {
+    let report = Stress::new(Backend::Blocking)
+        .run("blocking_cross_process")
+        .unwrap();
+    assert!(report.writes > 0);
+    assert_eq!(report.max_readers, 4);
+}
+
+#[test]
+fn async_cross_process() {
+    let report = Stress::new(Backend::Async)
+        .run("async_cross_process")
+        .unwrap();
+    assert!(report.writes > 0);
+    assert_eq!(report.max_readers, 4);
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn fcntl_cross_process() {
+    let report = Stress::new(Backend::Blocking)
+        .strategy(LockStrategy::Fcntl)
+        .run("fcntl_cross_process")
+        .unwrap();
+    assert!(report.writes > 0);
+    assert_eq!(report.max_readers, 4);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn dotlock_cross_process() {
+    let report = Stress::new(Backend::Async)
+        .strategy(LockStrategy::DotLock)
+        .run("dotlock_cross_process")
+        .unwrap();
+    assert!(report.writes > 0);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn broker_cross_process() {
+    let report = Stress::new(Backend::Blocking)
+        .strategy(LockStrategy::Broker)
+        .run("broker_cross_process")
+        .unwrap();
+    assert!(report.writes > 0);
+    assert_eq!(report.max_readers, 4);
+}
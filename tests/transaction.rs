@@ -0,0 +1,147 @@
+use async_fd_lock::blocking::LockRead;
+use async_fd_lock::transaction::{with_write_lock, with_write_lock_async};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Seek, SeekFrom, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::time::Duration;
+use tempfile::tempdir;
+
+fn open(path: &std::path::Path) -> File {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .unwrap()
+}
+
+#[test]
+fn commits_on_success() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+    fs::write(&path, "bongo").unwrap();
+
+    let len = with_write_lock(&open(&path), |guard| {
+        guard.seek(SeekFrom::End(0))?;
+        guard.write_all(b" cat")?;
+        guard.stream_position()
+    })
+    .unwrap();
+    assert_eq!(len, 9);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "bongo cat");
+}
+
+#[test]
+fn rolls_back_on_error() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+    fs::write(&path, "bongo cat").unwrap();
+
+    let file = open(&path);
+    let err = with_write_lock(&file, |guard| {
+        guard.inner().set_len(2)?;
+        guard.seek(SeekFrom::Start(10))?;
+        guard.write_all(b"partial")?;
+        Err::<(), _>(io::Error::other("failed"))
+    })
+    .unwrap_err();
+    assert_eq!(err.to_string(), "failed");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "bongo cat");
+    assert_eq!((&file).stream_position().unwrap(), 0);
+}
+
+#[test]
+fn rolls_back_on_panic() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+    fs::write(&path, "bongo cat").unwrap();
+
+    let file = open(&path);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        with_write_lock(&file, |guard| -> io::Result<()> {
+            guard.write_all(b"partial")?;
+            panic!("oops");
+        })
+    }));
+    assert!(result.is_err());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "bongo cat");
+    let _guard = File::open(&path).unwrap().try_lock_read().unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn rolls_back_on_failed_flush() {
+    use std::os::fd::{AsFd, BorrowedFd};
+
+    /// Writes through right away, but fails to flush.
+    struct FailingFlush(File);
+
+    impl AsFd for FailingFlush {
+        fn as_fd(&self) -> BorrowedFd<'_> {
+            self.0.as_fd()
+        }
+    }
+
+    impl Write for FailingFlush {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::Error::other("flush failed"))
+        }
+    }
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+    fs::write(&path, "bongo cat").unwrap();
+
+    let err = with_write_lock(FailingFlush(open(&path)), |guard| {
+        guard.write_all(b"partial")
+    })
+    .unwrap_err();
+    assert_eq!(err.to_string(), "flush failed");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "bongo cat");
+}
+
+#[tokio::test]
+async fn async_rolls_back_on_error_and_cancellation() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("lockfile");
+    fs::write(&path, "bongo cat").unwrap();
+    let open = || async {
+        tokio::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .await
+            .unwrap()
+    };
+
+    let err = with_write_lock_async(open().await, |guard| {
+        Box::pin(async move {
+            tokio::io::AsyncWriteExt::write_all(guard, b"partial").await?;
+            Err::<(), _>(io::Error::other("failed"))
+        })
+    })
+    .await
+    .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Other);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "bongo cat");
+
+    let canceled = tokio::time::timeout(
+        Duration::from_millis(100),
+        with_write_lock_async(open().await, |guard| {
+            Box::pin(async move {
+                // Still being written when the transaction is canceled.
+                tokio::io::AsyncWriteExt::write_all(guard, &[b'x'; 1 << 20]).await?;
+                std::future::pending::<io::Result<()>>().await
+            })
+        }),
+    )
+    .await;
+    assert!(canceled.is_err());
+    // Wait for the file to be restored in the background.
+    let file = tokio::fs::File::open(&path).await.unwrap();
+    let _guard = async_fd_lock::LockRead::lock_read(file).await.unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "bongo cat");
+}
@@ -0,0 +1,89 @@
+#![cfg(feature = "async")]
+
+use async_fd_lock::blocking::{LockRead, LockWrite};
+use async_fd_lock::watcher::{LockState, LockWatcher};
+use std::fs::File;
+use std::time::Duration;
+use tempfile::tempdir;
+use tokio::time::timeout;
+
+#[tokio::test]
+async fn reports_changes() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("foo.txt");
+    File::create(&path).unwrap();
+    let mut watcher = LockWatcher::new(&path)
+        .unwrap()
+        .probe_interval(Duration::from_millis(10));
+
+    assert_eq!(watcher.changed().await.unwrap(), LockState::Free);
+    let guard = File::open(&path).unwrap().lock_read().unwrap();
+    assert_eq!(watcher.changed().await.unwrap(), LockState::ReadLocked);
+    guard.release().unwrap();
+    let guard = File::create(&path).unwrap().lock_write().unwrap();
+    assert_eq!(watcher.changed().await.unwrap(), LockState::WriteLocked);
+    guard.release().unwrap();
+    assert_eq!(watcher.changed().await.unwrap(), LockState::Free);
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn wakes_on_close_and_sidecar_removal() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("foo.txt");
+    let guard = File::create(&path).unwrap().lock_write().unwrap();
+    // Only inotify can wake the watcher in time.
+    let mut watcher = LockWatcher::new(&path)
+        .unwrap()
+        .probe_interval(Duration::from_secs(3600));
+
+    let waiter = tokio::spawn(async move {
+        watcher.available(true).await.unwrap();
+        watcher
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!waiter.is_finished());
+    drop(guard);
+    let mut watcher = timeout(Duration::from_secs(5), waiter)
+        .await
+        .unwrap()
+        .unwrap();
+
+    // The file stays open, but the holder record goes away.
+    let file = File::create(&path).unwrap();
+    let mut guard = (&file).lock_write().unwrap();
+    guard.publish_holder("test").unwrap();
+    let waiter = tokio::spawn(async move { watcher.available(false).await.unwrap() });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!waiter.is_finished());
+    guard.release().unwrap();
+    timeout(Duration::from_secs(5), waiter)
+        .await
+        .unwrap()
+        .unwrap();
+    drop(file);
+}
+
+#[tokio::test]
+async fn watched_acquisition() {
+    use async_fd_lock::LockWrite;
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("foo.txt");
+    let guard = File::create(&path).unwrap().lock_write().unwrap();
+
+    let waiter = tokio::spawn({
+        let path = path.clone();
+        async move {
+            let file = tokio::fs::File::create(path).await.unwrap();
+            file.lock_write_watched().await.unwrap().release().unwrap();
+        }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!waiter.is_finished());
+    drop(guard);
+    timeout(Duration::from_secs(5), waiter)
+        .await
+        .unwrap()
+        .unwrap();
+}